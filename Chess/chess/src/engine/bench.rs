@@ -0,0 +1,109 @@
+use crate::engine::{
+    network::Network,
+    search::{find_best_move, SearchOptions, SearchSignals},
+    syzygy::Tablebases,
+    time::SearchLimits,
+};
+use crate::game::{board::Board, position::Position, tt::TranspositionTable};
+use std::time::Instant;
+
+pub const DEFAULT_BENCH_DEPTH: usize = 8;
+const BENCH_HASH: usize = 16; // MB
+
+/// Openings, middlegames and endgames searched by `bench`
+const BENCH_FENS: [&str; 20] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r3k2r/2pb1ppp/2pp1q2/p7/1nP1B3/1P2P3/P2N1PPP/R2QK2R w KQkq a6 0 14",
+    "4rrk1/2p1b1p1/p1p3q1/4p3/2P2n1p/1P1NR2P/PB3PP1/3R1QK1 b - - 2 24",
+    "r3qbrk/6p1/2b2pPp/p3pP1Q/PpPpP2P/3P1B2/2PB3K/R5R1 w - - 16 42",
+    "6k1/1R3p2/6p1/2Bp3p/3P2q1/P7/1P2rQ1K/5R2 b - - 4 44",
+    "8/8/1p2k1p1/3p3p/1p1P1P1P/1P2PK2/8/8 w - - 3 54",
+    "7r/2p3k1/1p1p1qp1/1P1Bp3/p1P2r1P/P7/4R3/Q4RK1 w - - 0 36",
+    "r1bq1rk1/pp2b1pp/n1pp1n2/3P1p2/2P1p3/2N1P2N/PP2BPPP/R1BQ1RK1 b - - 2 10",
+    "3r3k/2r4p/1p1b3q/p4P2/P2Pp3/1B2P3/3BQ1RP/6K1 w - - 3 87",
+    "2r4r/1p4k1/1Pnp4/3Qb1pq/8/4BpPp/5P2/2RR1BK1 w - - 0 42",
+    "4q1bk/6b1/7p/p1p4p/PNPpP2P/KN4P1/3Q4/4R3 b - - 0 37",
+    "2q3r1/1r2pk2/pp3pp1/2pP3p/P1Pb1BbP/1P4Q1/R3NPP1/4R1K1 w - - 2 34",
+    "1r2r2k/1b4q1/pp5p/2pPp1p1/P3Pn2/1P1B1Q1P/2R3P1/4BR1K b - - 1 37",
+    "8/8/8/5N2/8/p7/8/2NK3k w - - 0 1",
+    "8/6k1/6p1/8/7r/3P1KP1/8/8 w - - 0 1",
+];
+
+/// Searches every bench position to a fixed depth on a single thread, starting each one from an
+/// empty hash table, and prints the total node count and speed. The node count only depends on
+/// the search and evaluation code, so it serves as a signature of their behaviour
+pub fn run(network: &Network, depth: usize) -> u64 {
+    run_positions(&BENCH_FENS, network, depth)
+}
+
+fn run_positions(fens: &[&str], network: &Network, depth: usize) -> u64 {
+    let mut tt = TranspositionTable::new(BENCH_HASH);
+    let tablebases = Tablebases::default();
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..Default::default()
+    };
+    let options = SearchOptions {
+        info: false,
+        ..Default::default()
+    };
+
+    let mut nodes = 0;
+    let start = Instant::now();
+
+    for (i, fen) in fens.iter().enumerate() {
+        tt.clear();
+        let position = Position {
+            board: Board::from_fen(fen).unwrap(),
+            history: Vec::new(),
+        };
+        let result = find_best_move(
+            &position,
+            &limits,
+            &options,
+            &tt,
+            network,
+            &tablebases,
+            &SearchSignals::default(),
+        );
+
+        println!(
+            "Position {:>2}/{}: {} nodes, bestmove {}",
+            i + 1,
+            fens.len(),
+            result.nodes,
+            result.best_move
+        );
+        nodes += result.nodes;
+    }
+
+    let elapsed = start.elapsed().as_millis().max(1) as u64;
+    println!("{} nodes {} nps", nodes, nodes * 1000 / elapsed);
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unoptimized builds are slow, so the depth is kept low
+    const DEPTH: usize = 2;
+
+    #[test]
+    fn test_bench_deterministic() {
+        let network = Network::embedded();
+        let nodes = run(&network, DEPTH);
+        assert!(nodes > 0);
+        assert_eq!(run(&network, DEPTH), nodes);
+
+        // A position searched again must not benefit from the entries of the previous search
+        let fen = BENCH_FENS[0];
+        let single = run_positions(&[fen], &network, DEPTH);
+        assert_eq!(run_positions(&[fen, fen], &network, DEPTH), 2 * single);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::game::{board::Board, moves::Move, square::Square, zobrist::polyglot_key};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Size of a book entry: key, move, weight and learning data, all big endian
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(std::io::Error),
+    Size(usize),
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BookError::Io(err) => write!(f, "{}", err),
+            BookError::Size(size) => write!(
+                f,
+                "{} bytes is not a whole number of {} byte entries",
+                size, ENTRY_SIZE
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for BookError {
+    fn from(err: std::io::Error) -> Self {
+        BookError::Io(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BookEntry {
+    key: u64,
+    /// Polyglot move: destination in bits 0-5, origin in 6-11 and promotion piece in 12-14
+    raw_move: u16,
+    weight: u16,
+}
+
+/// Opening book in the Polyglot `.bin` format, a list of weighted moves sorted by position key
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, BookError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError::Size(bytes.len()));
+        }
+
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| BookEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                raw_move: u16::from_be_bytes([entry[8], entry[9]]),
+                weight: u16::from_be_bytes([entry[10], entry[11]]),
+            })
+            .collect();
+
+        // Books are written sorted, but a stable sort keeps their move order if one is not
+        entries.sort_by_key(|entry| entry.key);
+
+        Ok(Self { entries })
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Legal book moves of the position with their weights, moves the book suggests that are not
+    /// legal here (a key collision) are skipped
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let legal = board.generate_legal_moves();
+
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((decode_move(entry.raw_move, &legal)?, entry.weight)))
+            .collect()
+    }
+
+    /// Picks a book move with probability proportional to its weight, `random` being any
+    /// uniformly distributed number. Moves with zero weight are never played
+    pub fn pick(&self, board: &Board, random: u64) -> Option<Move> {
+        let moves = self.moves(board);
+        let total: u64 = moves.iter().map(|&(_, weight)| weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut target = random % total;
+        for (m, weight) in moves {
+            if target < weight as u64 {
+                return Some(m);
+            }
+            target -= weight as u64;
+        }
+
+        None
+    }
+}
+
+/// Finds the legal move a Polyglot move refers to. Castling is encoded as the king capturing its
+/// own rook, the same way as the moves of the engine
+fn decode_move(raw: u16, legal: &[Move]) -> Option<Move> {
+    let dest = Square::new((raw & 0x3F) as usize);
+    let src = Square::new(((raw >> 6) & 0x3F) as usize);
+    // Knight to queen, in the same order as the low bits of the promotion move kinds
+    let promotion = match (raw >> 12) & 0x7 {
+        0 => None,
+        piece @ 1..=4 => Some(piece - 1),
+        _ => return None,
+    };
+
+    legal.iter().copied().find(|m| {
+        if m.get_source() != src {
+            return false;
+        }
+
+        match m.get_type() {
+            kind if kind.is_promotion() => {
+                m.get_dest() == dest && promotion == Some(kind as u16 & 0b11)
+            }
+            _ => m.get_dest() == dest && promotion.is_none(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_polyglot_keys() {
+        for (fen, key) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                0x463b96181691fc9c,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                0x823c9b50fd114196,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+                0x0756b94461c50fb0,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+                0x662fafb965db29d4,
+            ),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                0x22a48b5a8e47ff78,
+            ),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+                0x652a607ca3f242c1,
+            ),
+            (
+                "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+                0x00fdd303c946bdd9,
+            ),
+            (
+                "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+                0x3c8123ea7b067637,
+            ),
+            (
+                "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+                0x5c3f9b829b279560,
+            ),
+        ] {
+            assert_eq!(polyglot_key(&Board::from_fen(fen).unwrap()), key, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_book_moves() {
+        let entry = |fen: &str, raw_move: u16, weight: u16| {
+            let mut bytes = polyglot_key(&Board::from_fen(fen).unwrap())
+                .to_be_bytes()
+                .to_vec();
+            bytes.extend(raw_move.to_be_bytes());
+            bytes.extend(weight.to_be_bytes());
+            bytes.extend([0; 4]);
+            bytes
+        };
+        let raw = |src: &str, dest: &str, promotion: u16| {
+            Square::from(dest).index() as u16
+                | (Square::from(src).index() as u16) << 6
+                | promotion << 12
+        };
+
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let promotion = "8/1P6/8/8/8/8/k7/4K3 w - - 0 1";
+        let bytes = [
+            entry(castling, raw("e1", "h1", 0), 3),
+            entry(castling, raw("e1", "a1", 0), 1),
+            entry(castling, raw("e1", "e3", 0), 5), // Illegal, as if from a colliding position
+            entry(promotion, raw("b7", "b8", 2), 0),
+            entry(promotion, raw("b7", "b8", 4), 1),
+        ]
+        .concat();
+        let book = Book::from_bytes(&bytes).unwrap();
+        assert_eq!(book.entry_count(), 5);
+
+        let board = Board::from_fen(castling).unwrap();
+        let moves: Vec<(String, u16)> = book
+            .moves(&board)
+            .into_iter()
+            .map(|(m, weight)| (m.to_string(), weight))
+            .collect();
+        assert_eq!(moves, [("e1g1".to_string(), 3), ("e1c1".to_string(), 1)]);
+        assert_eq!(book.pick(&board, 2).unwrap().to_string(), "e1g1");
+        assert_eq!(book.pick(&board, 3).unwrap().to_string(), "e1c1");
+
+        // The zero weight bishop promotion is never picked
+        let board = Board::from_fen(promotion).unwrap();
+        assert_eq!(book.moves(&board).len(), 2);
+        assert_eq!(book.pick(&board, 0).unwrap().to_string(), "b7b8q");
+        assert!(book.pick(&Board::default(), 0).is_none());
+
+        assert!(matches!(
+            Book::from_bytes(&bytes[1..]),
+            Err(BookError::Size(79))
+        ));
+    }
+}
@@ -0,0 +1,477 @@
+use crate::engine::{
+    movepick::is_tactical,
+    network::Network,
+    search::{find_best_move, SearchOptions, SearchResult, SearchSignals},
+    syzygy::Tablebases,
+    time::SearchLimits,
+};
+use crate::game::{
+    board::Board,
+    moves::Move,
+    pgn::Game,
+    piece::Colour,
+    position::Position,
+    tt::{TranspositionTable, MATE_BOUND},
+};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "Usage: chess datagen [games <n>] [nodes <n>] [threads <n>] [plies <n>] \
+                     [hash <mb>] [seed <n>] [output <path>] [pgn <path>]";
+
+/// Openings whose first search is more unbalanced than this are discarded
+const MAX_OPENING_EVAL: i32 = 1000;
+/// A side is declared the winner once the score stays above this for `WIN_PLIES` plies
+const WIN_SCORE: i32 = 2000;
+const WIN_PLIES: usize = 4;
+/// Past `DRAW_MIN_PLY` a game is drawn once the score stays within this for `DRAW_PLIES` plies
+const DRAW_SCORE: i32 = 10;
+const DRAW_PLIES: usize = 8;
+const DRAW_MIN_PLY: usize = 80;
+const MAX_GAME_PLIES: usize = 400;
+const PROGRESS_INTERVAL: usize = 100;
+
+/// Settings of the `datagen` subcommand
+struct DatagenConfig {
+    games: usize,
+    nodes: u64,
+    threads: usize,
+    random_plies: usize,
+    hash: usize,
+    seed: u64,
+    output: String,
+    /// Where to also save the games themselves, if anywhere
+    pgn: Option<String>,
+}
+
+impl Default for DatagenConfig {
+    fn default() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+
+        Self {
+            games: 1000,
+            nodes: 5000,
+            threads: 1,
+            random_plies: 8,
+            hash: 16,
+            seed,
+            output: "data.txt".to_string(),
+            pgn: None,
+        }
+    }
+}
+
+impl DatagenConfig {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        for pair in args.chunks(2) {
+            let [key, value] = pair else {
+                return Err(format!("Missing value for {}", pair[0]));
+            };
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid value for {}: {}", key, value))
+            };
+
+            match key.as_str() {
+                "games" => config.games = number()? as usize,
+                "nodes" => config.nodes = number()?.max(1),
+                "threads" => config.threads = (number()? as usize).max(1),
+                "plies" => config.random_plies = number()? as usize,
+                "hash" => config.hash = (number()? as usize).max(1),
+                "seed" => config.seed = number()?,
+                "output" => config.output = value.clone(),
+                "pgn" => config.pgn = Some(value.clone()),
+                _ => return Err(format!("Unknown option {}", key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Plays fixed node self-play games from random openings and writes every quiet position as
+/// `<fen> | <score> | <result>`, the text format read by common NNUE trainers. Score and result
+/// are given from White's point of view, the result being 1.0, 0.5 or 0.0.
+pub fn run(args: &[String]) {
+    let config = match DatagenConfig::parse(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let create = |path: &str| match File::create(path) {
+        Ok(file) => BufWriter::new(file),
+        Err(err) => {
+            eprintln!("Could not create {}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+    let file = create(&config.output);
+    let pgn = config.pgn.as_deref().map(create);
+
+    println!(
+        "Generating {} games at {} nodes per move on {} threads into {} (seed {})",
+        config.games, config.nodes, config.threads, config.output, config.seed
+    );
+
+    let network = Network::embedded();
+    let output = Mutex::new(file);
+    let pgn = Mutex::new(pgn);
+    let next_game = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let positions = AtomicU64::new(0);
+    let start = Instant::now();
+
+    thread::scope(|s| {
+        for id in 0..config.threads {
+            let (config, network, output, pgn) = (&config, &network, &output, &pgn);
+            let (next_game, finished, positions) = (&next_game, &finished, &positions);
+
+            s.spawn(move || {
+                let mut rng = Rng::new(config.seed ^ (id as u64 + 1).wrapping_mul(0x9E37_79B9));
+                let mut tt = TranspositionTable::new(config.hash);
+
+                while next_game.fetch_add(1, Ordering::Relaxed) < config.games {
+                    // A discarded opening is replaced within the same slot, so that every slot
+                    // ends with a finished game
+                    let (lines, game) = loop {
+                        if let Some(played) = play_game(config, network, &mut tt, &mut rng) {
+                            break played;
+                        }
+                    };
+
+                    let mut writer = output.lock().unwrap();
+                    for line in &lines {
+                        writeln!(writer, "{}", line).expect("Could not write training data");
+                    }
+                    drop(writer);
+
+                    if let Some(writer) = pgn.lock().unwrap().as_mut() {
+                        writeln!(writer, "{}", game.to_pgn()).expect("Could not write games");
+                    }
+
+                    let total = positions.fetch_add(lines.len() as u64, Ordering::Relaxed)
+                        + lines.len() as u64;
+                    let done = finished.fetch_add(1, Ordering::Relaxed) + 1;
+                    if done % PROGRESS_INTERVAL == 0 || done == config.games {
+                        let elapsed = start.elapsed().as_secs_f64().max(0.001);
+                        println!(
+                            "{}/{} games, {} positions, {:.0} positions/s",
+                            done,
+                            config.games,
+                            total,
+                            total as f64 / elapsed
+                        );
+                    }
+                }
+            });
+        }
+    });
+
+    output
+        .into_inner()
+        .unwrap()
+        .flush()
+        .expect("Could not write training data");
+    if let Some(mut writer) = pgn.into_inner().unwrap() {
+        writer.flush().expect("Could not write games");
+    }
+}
+
+/// Plays a single game, returning its positions already formatted along with the game itself.
+/// Games whose opening turns out to be too unbalanced are discarded
+fn play_game(
+    config: &DatagenConfig,
+    network: &Network,
+    tt: &mut TranspositionTable,
+    rng: &mut Rng,
+) -> Option<(Vec<String>, Game)> {
+    let (mut position, moves) = random_opening(rng, config.random_plies);
+    let mut game = Game::new(Board::default());
+    game.moves = moves;
+    let opening_plies = position.history.len();
+    let signals = SearchSignals::default();
+    let tablebases = Tablebases::default();
+
+    tt.clear();
+
+    let mut samples: Vec<(String, i32)> = Vec::new();
+    let mut adjudicator = Adjudicator::default();
+
+    let result = loop {
+        let board = &position.board;
+        let in_check = board.is_attacked_by(board.king_square(board.side), !board.side);
+
+        if board.generate_legal_moves().is_empty() {
+            break match (in_check, board.side) {
+                (false, _) => 0.5,
+                (true, Colour::White) => 0.0,
+                (true, Colour::Black) => 1.0,
+            };
+        }
+
+        if board.is_draw() || position.is_threefold() || position.history.len() >= MAX_GAME_PLIES {
+            break 0.5;
+        }
+
+        tt.increment_age();
+        let search = search_position(&position, config.nodes, tt, network, &tablebases, &signals);
+
+        let score = match board.side {
+            Colour::White => search.eval,
+            Colour::Black => -search.eval,
+        };
+
+        if position.history.len() == opening_plies && score.abs() > MAX_OPENING_EVAL {
+            return None;
+        }
+
+        if let Some(result) = adjudicator.update(score, position.history.len()) {
+            break result;
+        }
+
+        // Only quiet positions are useful to train a static evaluation
+        if !in_check && !is_tactical(search.best_move) && search.eval.abs() < MATE_BOUND {
+            samples.push((board.to_fen(), score));
+        }
+
+        position.make_move(search.best_move);
+        game.moves.push(search.best_move);
+    };
+
+    game.set_tag("Event", "Oxide datagen");
+    game.set_tag("White", "Oxide");
+    game.set_tag("Black", "Oxide");
+    game.result = match result {
+        1.0 => "1-0",
+        0.0 => "0-1",
+        _ => "1/2-1/2",
+    }
+    .to_string();
+
+    let lines = samples
+        .into_iter()
+        .map(|(fen, score)| format_line(&fen, score, result))
+        .collect();
+
+    Some((lines, game))
+}
+
+/// Fixed node search of a position. A node limit too small to complete the first iteration leaves
+/// no score, so the position is then searched again to depth 1 without a node limit
+fn search_position(
+    position: &Position,
+    nodes: u64,
+    tt: &TranspositionTable,
+    network: &Network,
+    tablebases: &Tablebases,
+    signals: &SearchSignals,
+) -> SearchResult {
+    let options = SearchOptions {
+        info: false,
+        ..Default::default()
+    };
+    let search = |limits: SearchLimits| {
+        signals.stop.store(false, Ordering::Relaxed);
+        find_best_move(
+            position, &limits, &options, tt, network, tablebases, signals,
+        )
+    };
+
+    let result = search(SearchLimits {
+        nodes: Some(nodes),
+        ..Default::default()
+    });
+    if result.depth > 0 {
+        return result;
+    }
+
+    search(SearchLimits {
+        depth: Some(1),
+        ..Default::default()
+    })
+}
+
+/// Keeps track of how long the score has been decisive or drawish, to end games early
+#[derive(Default)]
+struct Adjudicator {
+    win_plies: usize,
+    win_sign: i32,
+    draw_plies: usize,
+}
+
+impl Adjudicator {
+    /// Takes the score from White's point of view of the position after `ply` plies, and returns
+    /// the result once a decisive score lasts for the same side or a dead draw lasts late enough
+    fn update(&mut self, score: i32, ply: usize) -> Option<f64> {
+        if score.abs() >= WIN_SCORE {
+            self.win_plies = if score.signum() == self.win_sign {
+                self.win_plies + 1
+            } else {
+                1
+            };
+            self.win_sign = score.signum();
+        } else {
+            self.win_plies = 0;
+        }
+        if self.win_plies >= WIN_PLIES {
+            return Some(if self.win_sign > 0 { 1.0 } else { 0.0 });
+        }
+
+        self.draw_plies = if score.abs() <= DRAW_SCORE {
+            self.draw_plies + 1
+        } else {
+            0
+        };
+        if ply >= DRAW_MIN_PLY && self.draw_plies >= DRAW_PLIES {
+            return Some(0.5);
+        }
+
+        None
+    }
+}
+
+/// Training data line of a position, `<fen> | <score> | <result>`
+fn format_line(fen: &str, score: i32, result: f64) -> String {
+    format!("{} | {} | {:.1}", fen, score, result)
+}
+
+/// Plays `plies` random legal moves from the starting position, plus one more half of the time so
+/// that both sides get to move first, retrying until the opening does not end the game. The moves
+/// played are returned along with the position
+fn random_opening(rng: &mut Rng, plies: usize) -> (Position, Vec<Move>) {
+    let plies = plies + (rng.next() % 2) as usize;
+
+    'retry: loop {
+        let mut position = Position {
+            board: Board::default(),
+            history: Vec::new(),
+        };
+        let mut played = Vec::new();
+
+        for _ in 0..plies {
+            let moves = position.board.generate_legal_moves();
+            if moves.is_empty() {
+                continue 'retry;
+            }
+
+            let m = moves[(rng.next() % moves.len() as u64) as usize];
+            position.make_move(m);
+            played.push(m);
+        }
+
+        if !position.board.generate_legal_moves().is_empty() {
+            return (position, played);
+        }
+    }
+}
+
+/// Xorshift64* generator, good enough to pick opening moves
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_format() {
+        let fen = Board::default().to_fen();
+        assert_eq!(
+            format_line(&fen, -35, 0.5),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | -35 | 0.5"
+        );
+        assert!(format_line(&fen, 120, 1.0).ends_with(" | 120 | 1.0"));
+        assert!(format_line(&fen, 0, 0.0).ends_with(" | 0 | 0.0"));
+    }
+
+    #[test]
+    fn test_adjudication() {
+        // A win needs `WIN_PLIES` decisive scores in a row for the same side
+        let mut adjudicator = Adjudicator::default();
+        for ply in 1..WIN_PLIES {
+            assert_eq!(adjudicator.update(WIN_SCORE, ply), None);
+        }
+        assert_eq!(adjudicator.update(-WIN_SCORE, WIN_PLIES), None);
+        assert_eq!(adjudicator.update(WIN_SCORE - 1, WIN_PLIES + 1), None);
+        for ply in 1..WIN_PLIES {
+            assert_eq!(adjudicator.update(-WIN_SCORE, ply), None);
+        }
+        assert_eq!(adjudicator.update(-WIN_SCORE, WIN_PLIES), Some(0.0));
+
+        let mut adjudicator = Adjudicator::default();
+        for ply in 1..WIN_PLIES {
+            assert_eq!(adjudicator.update(MATE_BOUND, ply), None);
+        }
+        assert_eq!(adjudicator.update(WIN_SCORE, WIN_PLIES), Some(1.0));
+
+        // Drawish scores only end the game after `DRAW_MIN_PLY`
+        let mut adjudicator = Adjudicator::default();
+        for ply in 0..DRAW_MIN_PLY {
+            assert_eq!(adjudicator.update(DRAW_SCORE, ply), None);
+        }
+        assert_eq!(adjudicator.update(-DRAW_SCORE, DRAW_MIN_PLY), Some(0.5));
+
+        let mut adjudicator = Adjudicator::default();
+        for ply in DRAW_MIN_PLY..DRAW_MIN_PLY + DRAW_PLIES - 1 {
+            assert_eq!(adjudicator.update(0, ply), None);
+        }
+        assert_eq!(
+            adjudicator.update(DRAW_SCORE + 1, DRAW_MIN_PLY + DRAW_PLIES),
+            None
+        );
+        for ply in 0..DRAW_PLIES - 1 {
+            assert_eq!(adjudicator.update(0, DRAW_MIN_PLY + ply), None);
+        }
+        assert_eq!(adjudicator.update(0, DRAW_MIN_PLY + DRAW_PLIES), Some(0.5));
+    }
+
+    #[test]
+    fn test_search_position_scored() {
+        let position = Position {
+            board: Board::default(),
+            history: Vec::new(),
+        };
+        let tt = TranspositionTable::new(1);
+        let network = Network::embedded();
+
+        // One node is not enough to finish depth 1, the position must still get a real score
+        let result = search_position(
+            &position,
+            1,
+            &tt,
+            &network,
+            &Tablebases::default(),
+            &SearchSignals::default(),
+        );
+        assert!(result.depth >= 1);
+        assert!(result.eval.abs() < WIN_SCORE);
+        assert!(position
+            .board
+            .generate_legal_moves()
+            .contains(&result.best_move));
+    }
+}
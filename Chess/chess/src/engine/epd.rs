@@ -0,0 +1,330 @@
+use crate::engine::{
+    network::Network,
+    search::{find_best_move, SearchOptions, SearchResult, SearchSignals},
+    syzygy::Tablebases,
+    time::SearchLimits,
+};
+use crate::game::{
+    board::Board,
+    moves::Move,
+    position::Position,
+    san::{parse_san, to_san},
+    tt::TranspositionTable,
+};
+use std::fs;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: chess epd <file> [depth <n>] [movetime <ms>] [nodes <n>] \
+                     [threads <n>] [hash <mb>]";
+
+const DEFAULT_MOVETIME: u64 = 1000;
+
+/// Settings of the `epd` subcommand
+struct EpdConfig {
+    path: String,
+    limits: SearchLimits,
+    threads: usize,
+    hash: usize,
+}
+
+impl EpdConfig {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let (path, options) = args.split_first().ok_or("Missing EPD file")?;
+        let mut config = Self {
+            path: path.clone(),
+            limits: SearchLimits::default(),
+            threads: 1,
+            hash: 16,
+        };
+
+        for pair in options.chunks(2) {
+            let [key, value] = pair else {
+                return Err(format!("Missing value for {}", pair[0]));
+            };
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid value for {}: {}", key, value))
+            };
+
+            match key.as_str() {
+                "depth" => config.limits.depth = Some(number()? as usize),
+                "movetime" => config.limits.movetime = Some(number()?),
+                "nodes" => config.limits.nodes = Some(number()?),
+                "threads" => config.threads = (number()? as usize).max(1),
+                "hash" => config.hash = (number()? as usize).max(1),
+                _ => return Err(format!("Unknown option {}", key)),
+            }
+        }
+
+        let limits = &mut config.limits;
+        if limits.depth.is_none() && limits.movetime.is_none() && limits.nodes.is_none() {
+            limits.movetime = Some(DEFAULT_MOVETIME);
+        }
+
+        Ok(config)
+    }
+}
+
+/// A test position with the moves a correct search has to choose, or to avoid
+struct EpdPosition {
+    id: String,
+    board: Board,
+    best: Vec<Move>,
+    avoid: Vec<Move>,
+}
+
+impl EpdPosition {
+    /// Parses an EPD record: the first four FEN fields followed by `;` terminated operations.
+    /// `bm` and `am` moves are given in SAN, any operation other than those and `id` is ignored
+    fn parse(line: &str) -> Result<Self, String> {
+        // Fields may be separated by any run of spaces or tabs
+        let mut fields = [""; 4];
+        let mut operations = line.trim_start();
+        for field in &mut fields {
+            let end = operations
+                .find(char::is_whitespace)
+                .unwrap_or(operations.len());
+            *field = &operations[..end];
+            operations = operations[end..].trim_start();
+        }
+        let [placement, side, castling, en_passant] = fields;
+        if en_passant.is_empty() {
+            return Err("missing FEN fields".to_string());
+        }
+
+        let board = Board::from_fen(&format!(
+            "{} {} {} {}",
+            placement, side, castling, en_passant
+        ))
+        .map_err(|err| err.to_string())?;
+        let mut position = Self {
+            id: String::new(),
+            board,
+            best: Vec::new(),
+            avoid: Vec::new(),
+        };
+
+        for operation in operations.split(';').map(str::trim) {
+            let (opcode, operands) = operation
+                .split_once(char::is_whitespace)
+                .unwrap_or((operation, ""));
+            let moves = || {
+                operands
+                    .split_whitespace()
+                    .map(|san| parse_san(&board, san).map_err(|err| err.to_string()))
+                    .collect::<Result<Vec<Move>, String>>()
+            };
+
+            match opcode {
+                "id" => position.id = operands.trim().trim_matches('"').to_string(),
+                "bm" => position.best = moves()?,
+                "am" => position.avoid = moves()?,
+                _ => {}
+            }
+        }
+
+        if position.best.is_empty() && position.avoid.is_empty() {
+            return Err("no bm or am operation".to_string());
+        }
+
+        Ok(position)
+    }
+
+    fn is_solved_by(&self, m: Move) -> bool {
+        (self.best.is_empty() || self.best.contains(&m)) && !self.avoid.contains(&m)
+    }
+
+    fn search(
+        &self,
+        limits: &SearchLimits,
+        options: &SearchOptions,
+        tt: &TranspositionTable,
+        network: &Network,
+        tablebases: &Tablebases,
+    ) -> SearchResult {
+        let position = Position {
+            board: self.board,
+            history: Vec::new(),
+        };
+
+        find_best_move(
+            &position,
+            limits,
+            options,
+            tt,
+            network,
+            tablebases,
+            &SearchSignals::default(),
+        )
+    }
+
+    /// Describes whether the search found the right move, and how long it took
+    fn outcome(&self, result: &SearchResult) -> String {
+        if self.is_solved_by(result.best_move) {
+            return format!(
+                "solved in {} ms, {} nodes",
+                result.found_time.as_millis(),
+                result.found_nodes
+            );
+        }
+
+        let expected = |moves: &[Move]| -> Vec<String> {
+            moves.iter().map(|&m| to_san(&self.board, m)).collect()
+        };
+        if self.best.is_empty() {
+            format!("failed, am {}", expected(&self.avoid).join(" "))
+        } else {
+            format!("failed, bm {}", expected(&self.best).join(" "))
+        }
+    }
+}
+
+/// Searches every position of an EPD test suite with the same limits and reports which ones the
+/// engine solves, with the time and nodes it needed to settle on the right move
+pub fn run(args: &[String]) {
+    let config = match EpdConfig::parse(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let text = match fs::read_to_string(&config.path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", config.path, err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut positions = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match EpdPosition::parse(line.trim()) {
+            Ok(position) => positions.push(position),
+            Err(err) => {
+                eprintln!("{}:{}: {}", config.path, number + 1, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let network = Network::embedded();
+    let tablebases = Tablebases::default();
+    let mut tt = TranspositionTable::new(config.hash);
+    let options = SearchOptions {
+        threads: config.threads,
+        info: false,
+        ..Default::default()
+    };
+
+    let (mut solved, mut nodes, mut solve_time, mut solve_nodes) = (0, 0, Duration::ZERO, 0);
+
+    for (i, epd) in positions.iter().enumerate() {
+        tt.clear();
+        let result = epd.search(&config.limits, &options, &tt, &network, &tablebases);
+
+        nodes += result.nodes;
+        if epd.is_solved_by(result.best_move) {
+            solved += 1;
+            solve_time += result.found_time;
+            solve_nodes += result.found_nodes;
+        }
+
+        let id = if epd.id.is_empty() {
+            format!("#{}", i + 1)
+        } else {
+            epd.id.clone()
+        };
+        println!(
+            "{:<16} {:<8} depth {:<3} {}",
+            id,
+            to_san(&epd.board, result.best_move),
+            result.depth,
+            epd.outcome(&result)
+        );
+    }
+
+    let total = positions.len();
+    println!(
+        "Solved {}/{} ({:.1}%), {} nodes searched",
+        solved,
+        total,
+        solved as f64 * 100.0 / total.max(1) as f64,
+        nodes
+    );
+    if solved > 0 {
+        println!(
+            "Average time to solve {} ms, {} nodes",
+            solve_time.as_millis() / solved as u128,
+            solve_nodes / solved as u64
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_epd() {
+        let epd = EpdPosition::parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+        )
+        .unwrap();
+        assert_eq!(epd.id, "WAC.001");
+        assert_eq!(epd.best.len(), 1);
+        assert_eq!(epd.best[0].to_string(), "g3g6");
+        assert!(epd.is_solved_by(epd.best[0]));
+
+        let epd =
+            EpdPosition::parse("4k3/8/8/8/8/8/8/R3K2R w KQ - am O-O Ra8+; c0 \"x\";").unwrap();
+        assert!(epd.best.is_empty());
+        assert!(!epd.is_solved_by(epd.avoid[0]));
+        assert!(!epd.is_solved_by(epd.avoid[1]));
+        assert!(epd.is_solved_by(parse_san(&epd.board, "Ra7").unwrap()));
+
+        let epd = EpdPosition::parse(
+            "  2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1\tw  -\t-   bm\tQg6;  id \"WAC.001\";",
+        )
+        .unwrap();
+        assert_eq!(epd.id, "WAC.001");
+        assert_eq!(epd.best[0].to_string(), "g3g6");
+
+        assert!(EpdPosition::parse("4k3/8/8/8/8/8/8/R3K2R w KQ").is_err());
+        assert!(EpdPosition::parse("4k3/8/8/8/8/8/8/R3K2R w KQ - id \"x\";").is_err());
+        assert!(EpdPosition::parse("4k3/8/8/8/8/8/8/R3K2R w KQ - bm Rb8;").is_err());
+    }
+
+    #[test]
+    fn test_solve_avoid_moves() {
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let options = SearchOptions {
+            info: false,
+            ..Default::default()
+        };
+        let mut tt = TranspositionTable::new(1);
+        let network = Network::embedded();
+        let tablebases = Tablebases::default();
+
+        // Trading the queen for the rook gives the win away
+        let epd = EpdPosition::parse("4k3/3r4/8/8/8/8/8/3QK3 w - - am Qxd7+;").unwrap();
+        let result = epd.search(&limits, &options, &tt, &network, &tablebases);
+        assert!(epd.is_solved_by(result.best_move));
+        assert!(epd.outcome(&result).starts_with("solved"));
+
+        let epd = EpdPosition::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - am Ra8#;").unwrap();
+        tt.clear();
+        let result = epd.search(&limits, &options, &tt, &network, &tablebases);
+        assert!(!epd.is_solved_by(result.best_move));
+        assert_eq!(epd.outcome(&result), "failed, am Ra8#");
+    }
+}
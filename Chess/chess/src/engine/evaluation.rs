@@ -2,6 +2,7 @@ use crate::game::{
     bitboard::BitBoard,
     board::Board,
     piece::{Colour, Piece},
+    square::Square,
 };
 
 use super::network::{Accumulator, Network};
@@ -16,59 +17,122 @@ pub const PIECE_VALUES: [i32; 6] = [
     20000, // King (not actually used in evaluation)
 ];
 
-pub fn evaluate(board: &Board) -> i32 {
-    let white_king_sq = board.king_square(Colour::White).index();
-    let black_king_sq = board.king_square(Colour::Black).index();
+/// First layer of the network seen from each side, indexed by colour
+type AccumulatorPair = [Accumulator; 2];
 
-    let mut white_acc = Accumulator::default();
-    let mut black_acc = Accumulator::default();
+/// Accumulators of every position in the current search path. Each move only adds and
+/// removes the features of the pieces it touches, a perspective is refreshed from scratch
+/// only when its king changes bucket.
+pub struct AccumulatorStack<'a> {
+    network: &'a Network,
+    stack: Vec<AccumulatorPair>,
+}
 
-    fill_features(
-        board,
-        &mut white_acc,
-        &mut black_acc,
-        white_king_sq,
-        black_king_sq,
-    );
+impl<'a> AccumulatorStack<'a> {
+    pub fn new(network: &'a Network, board: &Board) -> Self {
+        let mut stack = Vec::with_capacity(256);
+        stack.push([refresh::<0>(network, board), refresh::<1>(network, board)]);
+        Self { network, stack }
+    }
 
-    let eval = if board.side == Colour::White {
-        Network::out(&white_acc, &black_acc)
-    } else {
-        Network::out(&black_acc, &white_acc)
-    };
+    /// Updates the accumulators with the last move made on `board`
+    pub fn push(&mut self, board: &Board) {
+        let mut pair = *self.stack.last().expect("Empty accumulator stack");
+        update::<0>(self.network, &mut pair[Colour::White as usize], board);
+        update::<1>(self.network, &mut pair[Colour::Black as usize], board);
+        self.stack.push(pair);
+    }
+
+    /// Restores the accumulators of the position before the last move
+    pub fn pop(&mut self) {
+        self.stack.pop();
+        debug_assert!(!self.stack.is_empty(), "Popped the root accumulators");
+    }
+
+    /// Evaluation of `board`, which must be the position of the last pushed move
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        let [white_acc, black_acc] = self.stack.last().expect("Empty accumulator stack");
+
+        let eval = if board.side == Colour::White {
+            self.network.out(white_acc, black_acc)
+        } else {
+            self.network.out(black_acc, white_acc)
+        };
+
+        scale(board, eval)
+    }
+}
+
+/// Input feature of `piece` on `square` from the perspective of `SIDE`, whose king is on `king_sq`
+fn feature<const SIDE: usize>(piece: Piece, square: Square, king_sq: usize) -> u16 {
+    let mut flip = if king_sq % 8 > 3 { 7 } else { 0 };
+    if SIDE == 1 {
+        flip ^= 56;
+    }
 
-    scale(board, eval)
+    let base = Network::get_base_index::<SIDE>(piece.colour() as usize, piece.index(), king_sq);
+    (base + (square.index() ^ flip)) as u16
 }
 
-fn fill_features(
-    board: &Board,
-    white_acc: &mut Accumulator,
-    black_acc: &mut Accumulator,
-    white_king_sq: usize,
-    black_king_sq: usize,
-) {
-    let wflip = if white_king_sq % 8 > 3 { 7 } else { 0 };
-    let bflip = if black_king_sq % 8 > 3 { 7 } else { 0 } ^ 56;
+fn perspective<const SIDE: usize>() -> Colour {
+    if SIDE == 0 {
+        Colour::White
+    } else {
+        Colour::Black
+    }
+}
 
-    let occ = board.sides[Colour::White as usize] | board.sides[Colour::Black as usize];
-    let mut occupied = occ;
+/// Builds the accumulator of `SIDE` from every piece on the board
+fn refresh<const SIDE: usize>(network: &Network, board: &Board) -> Accumulator {
+    let king_sq = board.king_square(perspective::<SIDE>()).index();
 
+    let mut features = Vec::with_capacity(32);
+    let mut occupied = board.sides[Colour::White as usize] | board.sides[Colour::Black as usize];
     while occupied != BitBoard::EMPTY {
         let sq = occupied.lsb();
         if let Some(piece) = board.piece_at(sq) {
-            let side = piece.colour() as usize;
-            let pc = piece.index();
+            features.push(feature::<SIDE>(piece, sq, king_sq));
+        }
+        occupied = occupied.pop_bit(sq);
+    }
+
+    let mut acc = Accumulator::new(network);
+    acc.update_multi(network, &features, &[]);
+    acc
+}
 
-            let wbase = Network::get_base_index::<0>(side, pc, white_king_sq) as u16;
-            let bbase = Network::get_base_index::<1>(side, pc, black_king_sq) as u16;
-            let wfeat = wbase + (sq.index() as u16 ^ wflip);
-            let bfeat = bbase + (sq.index() as u16 ^ bflip);
+/// Applies the pieces moved by the last move of `board` to the accumulator of `SIDE`
+fn update<const SIDE: usize>(network: &Network, acc: &mut Accumulator, board: &Board) {
+    let colour = perspective::<SIDE>();
+    let king_sq = board.king_square(colour).index();
+    let delta = &board.delta;
 
-            white_acc.update_multi(&[wfeat]);
-            black_acc.update_multi(&[bfeat]);
+    // Every feature depends on the king bucket, moving into another one requires a refresh
+    if let Some((piece, src)) = delta.removed[0] {
+        if piece.is_king()
+            && piece.colour() == colour
+            && Network::get_bucket::<SIDE>(src.index()) != Network::get_bucket::<SIDE>(king_sq)
+        {
+            *acc = refresh::<SIDE>(network, board);
+            return;
         }
-        occupied = occupied.pop_bit(sq);
     }
+
+    let mut adds = [0; 2];
+    let mut subs = [0; 2];
+    let mut add_count = 0;
+    let mut sub_count = 0;
+
+    for &(piece, sq) in delta.added.iter().flatten() {
+        adds[add_count] = feature::<SIDE>(piece, sq, king_sq);
+        add_count += 1;
+    }
+    for &(piece, sq) in delta.removed.iter().flatten() {
+        subs[sub_count] = feature::<SIDE>(piece, sq, king_sq);
+        sub_count += 1;
+    }
+
+    acc.update_multi(network, &adds[..add_count], &subs[..sub_count]);
 }
 
 fn scale(board: &Board, eval: i32) -> i32 {
@@ -81,3 +145,80 @@ fn scale(board: &Board, eval: i32) -> i32 {
     mat = 700 + mat / 32;
     eval * mat / 1024
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::network::SimdLevel;
+
+    #[test]
+    fn test_simd_levels() {
+        const FENS: [&str; 6] = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ];
+
+        let network = Network::embedded();
+        for fen in FENS {
+            let board = Board::from_fen(fen).unwrap();
+            let white = refresh::<0>(&network, &board);
+            let black = refresh::<1>(&network, &board);
+            let expected = network.out_with(SimdLevel::Scalar, &white, &black);
+
+            for level in SimdLevel::ALL.into_iter().filter(|l| l.is_supported()) {
+                assert_eq!(
+                    network.out_with(level, &white, &black),
+                    expected,
+                    "{fen}: {level:?} differs from the scalar output"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental_accumulators() {
+        // Castling, en passant, promotions and king moves across buckets
+        const GAMES: [(&str, &[&str]); 3] = [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                &[
+                    "e1g1", "e8c8", "a2a4", "b4a3", "f3f6", "g7f6", "g1h1", "c8b8",
+                ],
+            ),
+            (
+                "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+                &["g2h1q", "b7c8n", "d7e6", "e2e3", "e6d5", "e3d2"],
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                &["e1d1", "e8f7", "d1e2", "f7g6", "e2f3", "g6h5"],
+            ),
+        ];
+
+        let network = Network::embedded();
+        for (fen, moves) in GAMES {
+            let mut board = Board::from_fen(fen).unwrap();
+            let mut stack = AccumulatorStack::new(&network, &board);
+
+            for move_str in moves {
+                let m = board
+                    .generate_legal_moves()
+                    .into_iter()
+                    .find(|m| m.to_string() == *move_str)
+                    .unwrap_or_else(|| panic!("{fen}: {move_str} is not legal"));
+                board.make_move(m);
+                stack.push(&board);
+
+                assert_eq!(
+                    stack.evaluate(&board),
+                    AccumulatorStack::new(&network, &board).evaluate(&board),
+                    "{fen}: accumulators differ after {move_str}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::game::{
+    moves::Move,
+    piece::{Colour, Piece},
+    square::Square,
+};
+
+/// Upper bound of every history score, scores are kept inside it by the gravity formula
+const MAX_HISTORY: i32 = 16384;
+const MAX_BONUS: i32 = 1536;
+
+const PIECE_COUNT: usize = 12;
+
+/// Moved piece and destination square of a move, used to index continuation history
+pub type PieceTo = (Piece, Square);
+
+/// Quiet move ordering statistics of a single search thread
+pub struct History {
+    /// Butterfly history indexed by side, source and destination squares
+    butterfly: Box<[[[i32; Square::COUNT]; Square::COUNT]; 2]>,
+    /// Best reply found against the previous move, indexed by its piece and destination
+    countermoves: Box<[[Move; Square::COUNT]; PIECE_COUNT]>,
+    /// Score of a move given the move played one or two plies before, flattened as
+    /// [previous piece][previous destination][piece][destination]
+    continuation: Vec<i16>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            butterfly: Box::new([[[0; Square::COUNT]; Square::COUNT]; 2]),
+            countermoves: Box::new([[Move::default(); Square::COUNT]; PIECE_COUNT]),
+            continuation: vec![0; PIECE_COUNT * Square::COUNT * PIECE_COUNT * Square::COUNT],
+        }
+    }
+}
+
+impl History {
+    /// Ordering score of a quiet move, `piece` is the piece being moved and `previous`
+    /// the moves played one and two plies before, if any
+    pub fn quiet_score(
+        &self,
+        side: Colour,
+        m: Move,
+        piece: Piece,
+        previous: &[Option<PieceTo>; 2],
+    ) -> i32 {
+        let src = m.get_source().index();
+        let dest = m.get_dest();
+        let mut score = self.butterfly[side as usize][src][dest.index()];
+
+        for &prev in previous.iter().flatten() {
+            score += self.continuation[continuation_index(prev, (piece, dest))] as i32;
+        }
+
+        score
+    }
+
+    pub fn countermove(&self, previous: Option<PieceTo>) -> Option<Move> {
+        let (piece, square) = previous?;
+        let m = self.countermoves[piece as usize][square.index()];
+        (m != Move::default()).then_some(m)
+    }
+
+    /// Rewards the quiet move that caused a beta cutoff and penalises the quiet moves that
+    /// were searched before it without success
+    pub fn update(
+        &mut self,
+        side: Colour,
+        depth: usize,
+        best: (Move, Piece),
+        tried: &[(Move, Piece)],
+        previous: &[Option<PieceTo>; 2],
+    ) {
+        let bonus = (16 * (depth * depth) as i32).min(MAX_BONUS);
+
+        self.update_move(side, best, bonus, previous);
+        for &quiet in tried {
+            self.update_move(side, quiet, -bonus, previous);
+        }
+
+        if let Some((piece, square)) = previous[0] {
+            self.countermoves[piece as usize][square.index()] = best.0;
+        }
+    }
+
+    fn update_move(
+        &mut self,
+        side: Colour,
+        (m, piece): (Move, Piece),
+        bonus: i32,
+        previous: &[Option<PieceTo>; 2],
+    ) {
+        let (src, dest) = (m.get_source(), m.get_dest());
+
+        let entry = &mut self.butterfly[side as usize][src.index()][dest.index()];
+        *entry = apply_gravity(*entry, bonus);
+
+        for &prev in previous.iter().flatten() {
+            let entry = &mut self.continuation[continuation_index(prev, (piece, dest))];
+            *entry = apply_gravity(*entry as i32, bonus) as i16;
+        }
+    }
+}
+
+/// Moves the score towards the bonus, the closer it already is to the limit the smaller the step
+fn apply_gravity(score: i32, bonus: i32) -> i32 {
+    score + bonus - score * bonus.abs() / MAX_HISTORY
+}
+
+fn continuation_index((prev_piece, prev_sq): PieceTo, (piece, sq): PieceTo) -> usize {
+    ((prev_piece as usize * Square::COUNT + prev_sq.index()) * PIECE_COUNT + piece as usize)
+        * Square::COUNT
+        + sq.index()
+}
@@ -1,3 +1,13 @@
+pub mod bench;
+pub mod book;
+pub mod datagen;
+pub mod epd;
 pub mod evaluation;
+pub mod history;
+pub mod movepick;
 pub mod network;
+pub mod play;
 pub mod search;
+pub mod see;
+pub mod syzygy;
+pub mod time;
@@ -0,0 +1,259 @@
+use crate::engine::{
+    evaluation::PIECE_VALUES,
+    history::{History, PieceTo},
+    see::see,
+};
+use crate::game::{
+    board::Board,
+    moves::{Move, MoveKind, MAX_MOVES},
+};
+
+/// Stages of the move picker, in the order moves are returned
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Debug)]
+enum Stage {
+    TTMove,
+    GoodCaptures,
+    Killers,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Returns the legal moves of a position one at a time, best candidates first:
+/// transposition table move, captures winning material, killers and countermove,
+/// quiets sorted by history and finally captures losing material.
+///
+/// Moves are only generated and scored when their stage is reached, so a cutoff on an early
+/// move saves the generation and ordering work of the remaining ones.
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: Option<Move>,
+    refutations: [Option<Move>; 3],
+    captures: ScoredMoves,
+    quiets: ScoredMoves,
+    bad_captures: ScoredMoves,
+    quiets_generated: bool,
+    quiets_scored: bool,
+    skip_quiets: bool,
+}
+
+impl MovePicker {
+    pub fn new(
+        board: &Board,
+        tt_move: Option<Move>,
+        killers: [Move; 2],
+        countermove: Option<Move>,
+    ) -> Self {
+        let [first, second] = killers.map(|m| (m != Move::default()).then_some(m));
+        let mut picker = Self {
+            stage: Stage::TTMove,
+            tt_move: None,
+            refutations: [first, second, countermove],
+            captures: ScoredMoves::default(),
+            quiets: ScoredMoves::default(),
+            bad_captures: ScoredMoves::default(),
+            quiets_generated: false,
+            quiets_scored: false,
+            skip_quiets: false,
+        };
+
+        for m in board.generate_captures() {
+            picker.captures.push(m, mvv_lva(board, m));
+        }
+
+        // A quiet hash move can only be checked for legality against the quiets themselves
+        if let Some(tt_move) = tt_move {
+            if !is_tactical(tt_move) {
+                picker.generate_quiets(board);
+            }
+            let moves = if is_tactical(tt_move) {
+                &mut picker.captures
+            } else {
+                &mut picker.quiets
+            };
+            if moves.remove(tt_move) {
+                picker.tt_move = Some(tt_move);
+            }
+        }
+
+        picker
+    }
+
+    /// Picker for quiescence search, only captures and promotions that do not lose material
+    pub fn new_qsearch(board: &Board) -> Self {
+        let mut picker = Self::new(board, None, [Move::default(); 2], None);
+        picker.skip_quiets = true;
+        picker
+    }
+
+    /// Whether the position had no legal moves at all, generating the quiets if it takes that
+    pub fn is_empty(&mut self, board: &Board) -> bool {
+        if self.tt_move.is_some() || !self.captures.is_empty() {
+            return false;
+        }
+
+        self.generate_quiets(board);
+        self.quiets.is_empty()
+    }
+
+    fn generate_quiets(&mut self, board: &Board) {
+        if !self.quiets_generated {
+            for m in board.generate_quiets() {
+                self.quiets.push(m, 0);
+            }
+            self.quiets_generated = true;
+        }
+    }
+
+    pub fn next(
+        &mut self,
+        board: &Board,
+        history: &History,
+        previous: &[Option<PieceTo>; 2],
+    ) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TTMove => {
+                    self.stage = Stage::GoodCaptures;
+                    if self.tt_move.is_some() {
+                        return self.tt_move;
+                    }
+                }
+                Stage::GoodCaptures => match self.captures.pick_best() {
+                    Some((m, score)) => {
+                        if see(board, m, 0) {
+                            return Some(m);
+                        }
+                        self.bad_captures.push(m, score);
+                    }
+                    None if self.skip_quiets => self.stage = Stage::Done,
+                    None => self.stage = Stage::Killers,
+                },
+                Stage::Killers => {
+                    self.generate_quiets(board);
+                    let Some(slot) = self.refutations.iter_mut().find(|m| m.is_some()) else {
+                        self.stage = Stage::Quiets;
+                        continue;
+                    };
+
+                    let candidate = slot.take().expect("Refutation slot is not empty");
+                    if self.quiets.remove(candidate) {
+                        return Some(candidate);
+                    }
+                }
+                Stage::Quiets => {
+                    if !self.quiets_scored {
+                        self.score_quiets(board, history, previous);
+                    }
+
+                    match self.quiets.pick_best() {
+                        Some((m, _)) => return Some(m),
+                        None => self.stage = Stage::BadCaptures,
+                    }
+                }
+                Stage::BadCaptures => match self.bad_captures.pick_best() {
+                    Some((m, _)) => return Some(m),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    fn score_quiets(&mut self, board: &Board, history: &History, previous: &[Option<PieceTo>; 2]) {
+        for (m, score) in self.quiets.entries[..self.quiets.len].iter_mut() {
+            let piece = board
+                .piece_at(m.get_source())
+                .expect("No piece in source square");
+            *score = history.quiet_score(board.side, *m, piece, previous);
+        }
+
+        self.quiets_scored = true;
+    }
+}
+
+/// Captures and promotions, moves that change the material balance
+pub fn is_tactical(m: Move) -> bool {
+    m.get_type().is_capture() || m.get_type().is_promotion()
+}
+
+/// Most Valuable Victim - Least Valuable Attacker ordering score
+fn mvv_lva(board: &Board, m: Move) -> i32 {
+    let mut score = 0;
+
+    if m.get_type().is_capture() {
+        let src_piece = board.piece_at(m.get_source()).unwrap();
+        let dest_piece = board.piece_at(m.get_dest());
+
+        if let Some(dest_piece) = dest_piece {
+            score += 10 * PIECE_VALUES[dest_piece.index()] - PIECE_VALUES[src_piece.index()];
+        } else if m.get_type() == MoveKind::EnPassant {
+            score += PIECE_VALUES[0];
+        }
+    }
+
+    if m.get_type().is_promotion() {
+        let promo_piece = m.get_type().get_promotion(board.side);
+        score += PIECE_VALUES[promo_piece.index()];
+    }
+
+    score
+}
+
+/// Moves waiting to be picked along with their ordering scores, kept on the stack
+struct ScoredMoves {
+    entries: [(Move, i32); MAX_MOVES],
+    len: usize,
+}
+
+impl Default for ScoredMoves {
+    fn default() -> Self {
+        Self {
+            entries: [(Move::default(), 0); MAX_MOVES],
+            len: 0,
+        }
+    }
+}
+
+impl ScoredMoves {
+    fn push(&mut self, m: Move, score: i32) {
+        self.entries[self.len] = (m, score);
+        self.len += 1;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn swap_remove(&mut self, idx: usize) -> (Move, i32) {
+        let entry = self.entries[idx];
+        self.len -= 1;
+        self.entries[idx] = self.entries[self.len];
+        entry
+    }
+
+    /// Removes the given move, returning whether it was there
+    fn remove(&mut self, m: Move) -> bool {
+        match self.entries[..self.len]
+            .iter()
+            .position(|&(other, _)| other == m)
+        {
+            Some(idx) => {
+                self.swap_remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes and returns the move with the highest score
+    fn pick_best(&mut self) -> Option<(Move, i32)> {
+        let best = self.entries[..self.len]
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, &(_, score))| score)
+            .map(|(idx, _)| idx)?;
+
+        Some(self.swap_remove(best))
+    }
+}
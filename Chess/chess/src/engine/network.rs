@@ -1,8 +1,4 @@
-use crate::game::{
-    piece::{Colour, Piece},
-    square::Square,
-};
-use std::arch::x86_64::*;
+use std::{fmt, fs, path::Path, sync::OnceLock};
 
 // Square: 0-63
 // Piece: Pawn = 0, Knight = 1, Bishop = 2, Rook = 3, Queen = 4, King = 5
@@ -28,8 +24,38 @@ static BUCKETS: [usize; 64] = [
     3, 3, 3, 3, 7, 7, 7, 7,
 ];
 
-pub static NNUE: Network =
-    unsafe { std::mem::transmute(*include_bytes!("../../resources/nnue-net.bin")) };
+/// Network shipped inside the binary, used until another one is loaded through `EvalFile`
+static EMBEDDED_NETWORK: &[u8] = include_bytes!("../../resources/nnue-net.bin");
+
+/// Size of the serialised network, files may also include the trailing alignment padding
+const NETWORK_BYTES: usize = (INPUT_SIZE * NUM_BUCKETS + 3) * HL_SIZE * 2 + 2;
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(std::io::Error),
+    Size { expected: usize, found: usize },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Io(err) => write!(f, "{}", err),
+            NetworkError::Size { expected, found } => {
+                write!(
+                    f,
+                    "expected a {} byte network, found {} bytes",
+                    expected, found
+                )
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for NetworkError {
+    fn from(err: std::io::Error) -> Self {
+        NetworkError::Io(err)
+    }
+}
 
 #[repr(C)]
 pub struct Network {
@@ -39,13 +65,90 @@ pub struct Network {
     output_bias: i16,
 }
 
+/// Instruction sets the output layer can be computed with, every one of them gives exactly
+/// the same result as the scalar implementation
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SimdLevel {
+    Scalar,
+    Sse41,
+    Avx2,
+    Avx512,
+}
+
+impl SimdLevel {
+    pub const ALL: [Self; 4] = [Self::Scalar, Self::Sse41, Self::Avx2, Self::Avx512];
+
+    /// Best level supported by the running CPU, detected once
+    pub fn detect() -> Self {
+        static LEVEL: OnceLock<SimdLevel> = OnceLock::new();
+
+        *LEVEL.get_or_init(|| {
+            Self::ALL
+                .into_iter()
+                .rev()
+                .find(|level| level.is_supported())
+                .unwrap_or(Self::Scalar)
+        })
+    }
+
+    pub fn is_supported(self) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        {
+            match self {
+                Self::Scalar => true,
+                Self::Sse41 => is_x86_feature_detected!("sse4.1"),
+                Self::Avx2 => is_x86_feature_detected!("avx2"),
+                Self::Avx512 => is_x86_feature_detected!("avx512bw"),
+            }
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            self == Self::Scalar
+        }
+    }
+}
+
 impl Network {
-    pub fn out(boys: &Accumulator, opps: &Accumulator) -> i32 {
-        let weights = &NNUE.output_weights;
-        unsafe {
-            let sum = flatten(boys, &weights[0]) + flatten(opps, &weights[1]);
-            (sum / QA + i32::from(NNUE.output_bias)) * SCALE / QAB
+    pub fn embedded() -> Box<Self> {
+        Self::from_bytes(EMBEDDED_NETWORK).expect("Embedded network has an invalid layout")
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Box<Self>, NetworkError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Reads a network serialised as its little endian `i16` parameters in declaration order
+    pub fn from_bytes(bytes: &[u8]) -> Result<Box<Self>, NetworkError> {
+        if !(NETWORK_BYTES..=size_of::<Self>()).contains(&bytes.len()) {
+            return Err(NetworkError::Size {
+                expected: NETWORK_BYTES,
+                found: bytes.len(),
+            });
         }
+
+        // Every bit pattern is a valid `i16`, so a zeroed network filled byte by byte is sound
+        let mut network: Box<Self> = unsafe { boxed_and_zeroed() };
+        let params = unsafe {
+            std::slice::from_raw_parts_mut(
+                (network.as_mut() as *mut Self).cast::<u8>(),
+                NETWORK_BYTES,
+            )
+        };
+        params.copy_from_slice(&bytes[..NETWORK_BYTES]);
+
+        Ok(network)
+    }
+
+    pub fn out(&self, boys: &Accumulator, opps: &Accumulator) -> i32 {
+        self.out_with(SimdLevel::detect(), boys, opps)
+    }
+
+    /// Output of the network computed with a given instruction set, which must be supported
+    pub fn out_with(&self, level: SimdLevel, boys: &Accumulator, opps: &Accumulator) -> i32 {
+        let weights = &self.output_weights;
+        let sum = flatten(level, boys, &weights[0]).wrapping_add(flatten(level, opps, &weights[1]));
+        (sum / QA + i32::from(self.output_bias)) * SCALE / QAB
     }
 
     pub fn get_bucket<const SIDE: usize>(mut king_sq: usize) -> usize {
@@ -76,7 +179,7 @@ pub struct Accumulator {
 }
 
 impl Accumulator {
-    pub fn update_multi(&mut self, adds: &[u16]) {
+    pub fn update_multi(&mut self, network: &Network, adds: &[u16], subs: &[u16]) {
         const REGS: usize = 8;
         const PER: usize = REGS * 16;
 
@@ -90,13 +193,21 @@ impl Accumulator {
             }
 
             for &add in adds {
-                let weights = &NNUE.accumulator_weights[usize::from(add)];
+                let weights = &network.accumulator_weights[usize::from(add)];
 
                 for (j, reg) in regs.iter_mut().enumerate() {
                     *reg += weights.vals[offset + j];
                 }
             }
 
+            for &sub in subs {
+                let weights = &network.accumulator_weights[usize::from(sub)];
+
+                for (j, reg) in regs.iter_mut().enumerate() {
+                    *reg -= weights.vals[offset + j];
+                }
+            }
+
             for (j, reg) in regs.iter().enumerate() {
                 self.vals[offset + j] = *reg;
             }
@@ -104,76 +215,123 @@ impl Accumulator {
     }
 }
 
-impl Default for Accumulator {
-    fn default() -> Self {
-        NNUE.accumulator_biases
+impl Accumulator {
+    /// Accumulator of a position without pieces
+    pub fn new(network: &Network) -> Self {
+        network.accumulator_biases
     }
 }
 
-pub struct EvalEntry {
-    pub bbs: [u64; 8],
-    pub white: Accumulator,
-    pub black: Accumulator,
-}
+/// SCReLU activation multiplied by the output weights, computed as `v * (v * w)` where the
+/// inner product is truncated to 16 bits so that it fits the SIMD lanes
+fn flatten(level: SimdLevel, acc: &Accumulator, weights: &Accumulator) -> i32 {
+    debug_assert!(
+        level.is_supported(),
+        "{level:?} is not supported by this CPU"
+    );
+
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        match level {
+            SimdLevel::Scalar => flatten_scalar(acc, weights),
+            SimdLevel::Sse41 => x86::flatten_sse41(acc, weights),
+            SimdLevel::Avx2 => x86::flatten_avx2(acc, weights),
+            SimdLevel::Avx512 => x86::flatten_avx512(acc, weights),
+        }
+    }
 
-pub struct EvalTable {
-    pub table: Box<[[EvalEntry; 2 * NUM_BUCKETS]; 2 * NUM_BUCKETS]>,
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let _ = level;
+        flatten_scalar(acc, weights)
+    }
 }
 
-impl Default for EvalTable {
-    fn default() -> Self {
-        let mut table: Box<[[EvalEntry; 2 * NUM_BUCKETS]; 2 * NUM_BUCKETS]> =
-            unsafe { boxed_and_zeroed() };
+/// Reference implementation, the SIMD versions wrap on overflow in the same way
+fn flatten_scalar(acc: &Accumulator, weights: &Accumulator) -> i32 {
+    acc.vals
+        .iter()
+        .zip(weights.vals.iter())
+        .fold(0i32, |sum, (&v, &w)| {
+            let v = v.clamp(0, QA as i16);
+            sum.wrapping_add(i32::from(v) * i32::from(v.wrapping_mul(w)))
+        })
+}
 
-        for row in table.iter_mut() {
-            for entry in row.iter_mut() {
-                entry.white = Accumulator::default();
-                entry.black = Accumulator::default();
-            }
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{Accumulator, HL_SIZE, QA};
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "sse4.1")]
+    pub unsafe fn flatten_sse41(acc: &Accumulator, weights: &Accumulator) -> i32 {
+        const CHUNK: usize = 8;
+
+        let mut sum = _mm_setzero_si128();
+        let min = _mm_setzero_si128();
+        let max = _mm_set1_epi16(QA as i16);
+
+        for i in 0..HL_SIZE / CHUNK {
+            let mut v = _mm_load_si128(acc.vals.as_ptr().add(i * CHUNK).cast());
+            v = _mm_min_epi16(_mm_max_epi16(v, min), max);
+            let w = _mm_load_si128(weights.vals.as_ptr().add(i * CHUNK).cast());
+            let product = _mm_madd_epi16(v, _mm_mullo_epi16(v, w));
+            sum = _mm_add_epi32(sum, product);
         }
 
-        Self { table }
+        horizontal_sum_i32_128(sum)
     }
-}
 
-pub fn screlu(x: i16) -> i32 {
-    i32::from(x.clamp(0, QA as i16)).pow(2)
-}
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn flatten_avx2(acc: &Accumulator, weights: &Accumulator) -> i32 {
+        const CHUNK: usize = 16;
 
-pub unsafe fn flatten(acc: &Accumulator, weights: &Accumulator) -> i32 {
-    const CHUNK: usize = 16;
+        let mut sum = _mm256_setzero_si256();
+        let min = _mm256_setzero_si256();
+        let max = _mm256_set1_epi16(QA as i16);
 
-    let mut sum = _mm256_setzero_si256();
-    let min = _mm256_setzero_si256();
-    let max = _mm256_set1_epi16(QA as i16);
+        for i in 0..HL_SIZE / CHUNK {
+            let mut v = _mm256_load_si256(acc.vals.as_ptr().add(i * CHUNK).cast());
+            v = _mm256_min_epi16(_mm256_max_epi16(v, min), max);
+            let w = _mm256_load_si256(weights.vals.as_ptr().add(i * CHUNK).cast());
+            let product = _mm256_madd_epi16(v, _mm256_mullo_epi16(v, w));
+            sum = _mm256_add_epi32(sum, product);
+        }
 
-    for i in 0..HL_SIZE / CHUNK {
-        let mut v = load_i16s(acc, i * CHUNK);
-        v = _mm256_min_epi16(_mm256_max_epi16(v, min), max);
-        let w = load_i16s(weights, i * CHUNK);
-        let product = _mm256_madd_epi16(v, _mm256_mullo_epi16(v, w));
-        sum = _mm256_add_epi32(sum, product);
+        let upper_128 = _mm256_extracti128_si256::<1>(sum);
+        let lower_128 = _mm256_castsi256_si128(sum);
+        horizontal_sum_i32_128(_mm_add_epi32(upper_128, lower_128))
     }
 
-    horizontal_sum_i32(sum)
-}
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn flatten_avx512(acc: &Accumulator, weights: &Accumulator) -> i32 {
+        const CHUNK: usize = 32;
 
-#[inline]
-unsafe fn load_i16s(acc: &Accumulator, start_idx: usize) -> __m256i {
-    _mm256_load_si256(acc.vals.as_ptr().add(start_idx).cast())
-}
+        let mut sum = _mm512_setzero_si512();
+        let min = _mm512_setzero_si512();
+        let max = _mm512_set1_epi16(QA as i16);
+
+        for i in 0..HL_SIZE / CHUNK {
+            let mut v = _mm512_load_si512(acc.vals.as_ptr().add(i * CHUNK).cast());
+            v = _mm512_min_epi16(_mm512_max_epi16(v, min), max);
+            let w = _mm512_load_si512(weights.vals.as_ptr().add(i * CHUNK).cast());
+            let product = _mm512_madd_epi16(v, _mm512_mullo_epi16(v, w));
+            sum = _mm512_add_epi32(sum, product);
+        }
 
-#[inline]
-unsafe fn horizontal_sum_i32(sum: __m256i) -> i32 {
-    let upper_128 = _mm256_extracti128_si256::<1>(sum);
-    let lower_128 = _mm256_castsi256_si128(sum);
-    let sum_128 = _mm_add_epi32(upper_128, lower_128);
-    let upper_64 = _mm_unpackhi_epi64(sum_128, sum_128);
-    let sum_64 = _mm_add_epi32(upper_64, sum_128);
-    let upper_32 = _mm_shuffle_epi32::<0b00_00_00_01>(sum_64);
-    let sum_32 = _mm_add_epi32(upper_32, sum_64);
-
-    _mm_cvtsi128_si32(sum_32)
+        _mm512_reduce_add_epi32(sum)
+    }
+
+    #[inline]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn horizontal_sum_i32_128(sum: __m128i) -> i32 {
+        let upper_64 = _mm_unpackhi_epi64(sum, sum);
+        let sum_64 = _mm_add_epi32(upper_64, sum);
+        let upper_32 = _mm_shuffle_epi32::<0b00_00_00_01>(sum_64);
+        let sum_32 = _mm_add_epi32(upper_32, sum_64);
+
+        _mm_cvtsi128_si32(sum_32)
+    }
 }
 
 pub unsafe fn boxed_and_zeroed<T>() -> Box<T> {
@@ -184,3 +342,22 @@ pub unsafe fn boxed_and_zeroed<T>() -> Box<T> {
     }
     Box::from_raw(ptr.cast())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_size_validation() {
+        assert!(Network::from_bytes(EMBEDDED_NETWORK).is_ok());
+        assert!(Network::from_bytes(&EMBEDDED_NETWORK[..NETWORK_BYTES]).is_ok());
+
+        for len in [0, NETWORK_BYTES - 1, size_of::<Network>() + 1] {
+            let bytes = vec![0; len];
+            assert!(matches!(
+                Network::from_bytes(&bytes),
+                Err(NetworkError::Size { found, .. }) if found == len
+            ));
+        }
+    }
+}
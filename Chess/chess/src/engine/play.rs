@@ -0,0 +1,368 @@
+use crate::engine::{
+    network::Network,
+    search::{find_best_move, format_score, SearchOptions, SearchSignals},
+    syzygy::Tablebases,
+    time::SearchLimits,
+};
+use crate::game::{
+    board::{Board, Undo},
+    moves::Move,
+    piece::Colour,
+    position::Position,
+    san::{parse_san, to_san},
+    tt::TranspositionTable,
+};
+use std::io::{self, BufRead, Write};
+
+const USAGE: &str = "Usage: chess play [white|black] [depth <n>] [movetime <ms>] [nodes <n>]";
+
+const HELP: &str = "Enter moves in SAN (Nf3, O-O, exd8=Q) or UCI (g1f3, e1g1, e7d8q). Commands:
+  undo            take back your last move and the engine's reply
+  flip            turn the board around
+  moves           list the legal moves
+  depth <n>       let the engine search to a fixed depth
+  movetime <ms>   let the engine think for a fixed time per move
+  nodes <n>       let the engine search a fixed number of nodes
+  help            show this help
+  quit            leave the game";
+
+const DEFAULT_MOVETIME: u64 = 1000;
+const PLAY_HASH: usize = 16; // MB
+
+/// Settings of the `play` subcommand
+struct PlayConfig {
+    human: Colour,
+    limits: SearchLimits,
+}
+
+impl PlayConfig {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut config = Self {
+            human: Colour::White,
+            limits: SearchLimits::default(),
+        };
+
+        let options = match args.first().map(String::as_str) {
+            Some("white") => &args[1..],
+            Some("black") => {
+                config.human = Colour::Black;
+                &args[1..]
+            }
+            _ => args,
+        };
+
+        for pair in options.chunks(2) {
+            let [key, value] = pair else {
+                return Err(format!("Missing value for {}", pair[0]));
+            };
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid value for {}: {}", key, value))
+            };
+
+            match key.as_str() {
+                "depth" => config.limits.depth = Some(number()? as usize),
+                "movetime" => config.limits.movetime = Some(number()?),
+                "nodes" => config.limits.nodes = Some(number()?),
+                _ => return Err(format!("Unknown option {}", key)),
+            }
+        }
+
+        let limits = &mut config.limits;
+        if limits.depth.is_none() && limits.movetime.is_none() && limits.nodes.is_none() {
+            limits.movetime = Some(DEFAULT_MOVETIME);
+        }
+
+        Ok(config)
+    }
+}
+
+/// A game between a human at the terminal and the engine
+struct Game {
+    position: Position,
+    /// Moves played so far along with what is needed to take them back
+    played: Vec<(Move, Undo)>,
+    human: Colour,
+    flipped: bool,
+    limits: SearchLimits,
+    tt: TranspositionTable,
+    network: Box<Network>,
+    tablebases: Tablebases,
+    /// Shown below the board on the next redraw
+    status: String,
+}
+
+impl Game {
+    fn new(config: PlayConfig) -> Self {
+        Self {
+            position: Position {
+                board: Board::default(),
+                history: Vec::new(),
+            },
+            played: Vec::new(),
+            human: config.human,
+            flipped: config.human == Colour::Black,
+            limits: config.limits,
+            tt: TranspositionTable::new(PLAY_HASH),
+            network: Network::embedded(),
+            tablebases: Tablebases::default(),
+            status: "Type help for the list of commands".to_string(),
+        }
+    }
+
+    fn draw(&self) {
+        self.position.board.draw_board(self.flipped);
+        println!();
+        if let Some(&(m, _)) = self.played.last() {
+            let board = &self.position.board;
+            let (number, dots) = match board.side {
+                Colour::White => (board.fullmoves - 1, "..."),
+                Colour::Black => (board.fullmoves, "."),
+            };
+            println!("Last move: {}{} {}", number, dots, m);
+        }
+        println!("{}", self.status);
+    }
+
+    fn play(&mut self, m: Move) {
+        let undo = self.position.make_move(m);
+        self.played.push((m, undo));
+    }
+
+    fn engine_move(&mut self) {
+        let options = SearchOptions {
+            info: false,
+            ..Default::default()
+        };
+        let result = find_best_move(
+            &self.position,
+            &self.limits,
+            &options,
+            &self.tt,
+            &self.network,
+            &self.tablebases,
+            &SearchSignals::default(),
+        );
+
+        self.status = format!(
+            "Oxide plays {} (depth {}, {})",
+            to_san(&self.position.board, result.best_move),
+            result.depth,
+            format_score(result.eval)
+        );
+        self.play(result.best_move);
+    }
+
+    /// Takes moves back until it is the human's turn again, so both the last human move and the
+    /// engine's reply to it are undone
+    fn undo(&mut self) -> bool {
+        // White moves first, so the human's first move is the first or second one of the game
+        if self.played.len() <= self.human as usize {
+            return false;
+        }
+
+        while let Some((m, undo)) = self.played.pop() {
+            self.position.unmake_move(m, undo);
+            if self.position.board.side == self.human {
+                break;
+            }
+        }
+        true
+    }
+
+    /// Handles one line of input, returning false when the player leaves
+    fn command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let board = &self.position.board;
+
+        self.status = match tokens.as_slice() {
+            [] => String::new(),
+            ["quit" | "exit"] => return false,
+            ["help"] => HELP.to_string(),
+            ["flip"] => {
+                self.flipped = !self.flipped;
+                String::new()
+            }
+            ["moves"] => {
+                let moves: Vec<String> = board
+                    .generate_legal_moves()
+                    .iter()
+                    .map(|&m| to_san(board, m))
+                    .collect();
+                format!("Legal moves: {}", moves.join(" "))
+            }
+            ["undo"] => {
+                if self.undo() {
+                    "Move taken back".to_string()
+                } else {
+                    "Nothing to undo".to_string()
+                }
+            }
+            [key @ ("depth" | "movetime" | "nodes"), value] => match value.parse::<u64>() {
+                Ok(0) | Err(_) => format!("Invalid value for {}: {}", key, value),
+                Ok(n) => {
+                    self.limits = SearchLimits::default();
+                    match *key {
+                        "depth" => self.limits.depth = Some(n as usize),
+                        "movetime" => self.limits.movetime = Some(n),
+                        _ => self.limits.nodes = Some(n),
+                    }
+                    format!("The engine now searches with {} {}", key, n)
+                }
+            },
+            [text] => match game_over(&self.position) {
+                Some(result) => format!("{}, undo or quit", result),
+                None => match parse_move(board, text) {
+                    Ok(m) => {
+                        self.play(m);
+                        String::new()
+                    }
+                    Err(err) => err,
+                },
+            },
+            _ => format!("Unknown command {}, type help for the list", line.trim()),
+        };
+
+        true
+    }
+}
+
+/// Reads a move in UCI notation, falling back to SAN
+fn parse_move(board: &Board, text: &str) -> Result<Move, String> {
+    let legal = board.generate_legal_moves();
+    match legal.iter().find(|m| m.to_string() == text) {
+        Some(&m) => Ok(m),
+        None => parse_san(board, text).map_err(|err| err.to_string()),
+    }
+}
+
+/// Describes how the game ended, if it did
+fn game_over(position: &Position) -> Option<String> {
+    let board = &position.board;
+
+    if board.generate_legal_moves().is_empty() {
+        return Some(
+            if board.is_attacked_by(board.king_square(board.side), !board.side) {
+                match board.side {
+                    Colour::White => "Checkmate, Black wins",
+                    Colour::Black => "Checkmate, White wins",
+                }
+            } else {
+                "Stalemate"
+            }
+            .to_string(),
+        );
+    }
+
+    if board.is_draw() {
+        return Some(
+            if board.halfmoves >= 100 {
+                "Draw by the fifty move rule"
+            } else {
+                "Draw by insufficient material"
+            }
+            .to_string(),
+        );
+    }
+
+    position
+        .is_threefold()
+        .then(|| "Draw by threefold repetition".to_string())
+}
+
+/// Plays a game against the engine in the terminal. The board is redrawn after every move, the
+/// engine replies as soon as it is its turn, and once the game is over only `undo` and `quit` are
+/// of any use
+pub fn run(args: &[String]) {
+    let config = match PlayConfig::parse(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let mut game = Game::new(config);
+    let mut lines = io::stdin().lock().lines();
+
+    loop {
+        let over = game_over(&game.position);
+        if over.is_none() && game.position.board.side != game.human {
+            game.draw();
+            println!("Thinking...");
+            game.engine_move();
+            continue;
+        }
+
+        if let Some(result) = over {
+            game.status = result;
+        }
+        game.draw();
+        print!("> ");
+        io::stdout().flush().unwrap();
+
+        match lines.next() {
+            Some(Ok(line)) if game.command(&line) => {}
+            _ => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_over() {
+        for (fen, expected) in [
+            (
+                "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+                None,
+            ),
+            (
+                "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+                Some("Checkmate, Black wins"),
+            ),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", Some("Stalemate")),
+            (
+                "4k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+                Some("Draw by insufficient material"),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w - - 100 80",
+                Some("Draw by the fifty move rule"),
+            ),
+        ] {
+            let position = Position {
+                board: Board::from_fen(fen).unwrap(),
+                history: Vec::new(),
+            };
+            assert_eq!(game_over(&position).as_deref(), expected, "{}", fen);
+        }
+
+        let mut position = Position {
+            board: Board::default(),
+            history: Vec::new(),
+        };
+        for (i, m) in ["g1f3", "g8f6", "f3g1", "f6g8"]
+            .iter()
+            .cycle()
+            .take(8)
+            .enumerate()
+        {
+            assert_eq!(game_over(&position), None, "ply {}", i);
+            let m = parse_move(&position.board, m).unwrap();
+            position.make_move(m);
+        }
+        assert_eq!(
+            game_over(&position).as_deref(),
+            Some("Draw by threefold repetition")
+        );
+
+        // Moves are accepted in either notation
+        let board = Board::default();
+        assert_eq!(parse_move(&board, "Nf3"), parse_move(&board, "g1f3"));
+        assert!(parse_move(&board, "e2e5").is_err());
+    }
+}
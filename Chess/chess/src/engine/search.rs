@@ -1,88 +1,722 @@
-use super::evaluation::evaluate;
-use crate::engine::evaluation::PIECE_VALUES;
-use crate::game::moves::MoveKind;
-use crate::game::{board::Board, moves::Move, piece::Colour};
-use std::sync::mpsc;
+use crate::engine::evaluation::{AccumulatorStack, PIECE_VALUES};
+use crate::engine::history::{History, PieceTo};
+use crate::engine::movepick::{is_tactical, MovePicker};
+use crate::engine::network::Network;
+use crate::engine::syzygy::{Tablebases, Wdl};
+use crate::engine::time::{SearchLimits, TimeManager};
+use crate::game::tt::{TTFlag, TranspositionTable, MATE, MATE_BOUND, MAX_PLY, TB_WIN};
+use crate::game::{board::Board, moves::Move, piece::Piece, position::Position, zobrist::ZHash};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::thread;
+use std::time::{Duration, Instant};
 
-const INF: i32 = 16384;
-const MATE: i32 = 16300;
+pub const MAX_DEPTH: usize = 64;
+const INF: i32 = MATE + 1;
+const DRAW: i32 = 0;
 
-pub fn find_best_move(board: &Board, mut depth: usize) -> Move {
-    let mut moves = board.generate_legal_moves();
-    if moves.is_empty() {
-        return Move::default();
+/// Margin added to the captured piece value before discarding a capture in quiescence
+const DELTA_MARGIN: i32 = 200;
+
+const RFP_MAX_DEPTH: usize = 6;
+const RFP_MARGIN: i32 = 80;
+const RAZORING_MAX_DEPTH: usize = 3;
+const RAZORING_MARGIN: i32 = 250;
+const FUTILITY_MAX_DEPTH: usize = 4;
+const FUTILITY_MARGIN: i32 = 120;
+const NULL_MOVE_MIN_DEPTH: usize = 3;
+const LMR_MIN_DEPTH: usize = 3;
+const LMR_MIN_MOVES: usize = 3;
+
+/// Number of nodes searched between each time check
+const TIME_CHECK_INTERVAL: u64 = 2048;
+
+/// Search parameters configurable through UCI `setoption`
+#[derive(Clone, Copy, Debug)]
+pub struct SearchOptions {
+    pub threads: usize,
+    pub multipv: usize,
+    pub move_overhead: u64,
+    pub null_move: bool,
+    pub lmr: bool,
+    pub reverse_futility: bool,
+    pub futility: bool,
+    pub razoring: bool,
+    pub check_extensions: bool,
+    /// Write castling moves as the king taking its rook, as UCI expects in Chess960 games
+    pub chess960: bool,
+    /// Print UCI `info` lines after every iteration
+    pub info: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            multipv: 1,
+            move_overhead: 10,
+            null_move: true,
+            lmr: true,
+            reverse_futility: true,
+            futility: true,
+            razoring: true,
+            check_extensions: true,
+            chess960: false,
+            info: true,
+        }
+    }
+}
+
+/// Flags shared between the UCI thread and the search.
+///
+/// `stop` aborts the search as soon as possible, while `ponder` keeps the time manager
+/// disabled until the GUI sends `ponderhit`, from which the time limits then count
+#[derive(Debug, Default)]
+pub struct SearchSignals {
+    pub stop: AtomicBool,
+    pub ponder: AtomicBool,
+    pub ponderhit: Mutex<Option<Instant>>,
+}
+
+impl SearchSignals {
+    /// Resets the signals before starting a new search
+    pub fn start(&self, ponder: bool) {
+        self.stop.store(false, Ordering::SeqCst);
+        *self.ponderhit.lock().unwrap() = None;
+        self.ponder.store(ponder, Ordering::SeqCst);
     }
 
-    if board.occupied() <= 18 {
-        depth = 7;
-        if board.occupied() <= 12 {
-            depth = 8;
+    /// Turns a ponder search into a normal one, its time budget starting now
+    pub fn ponder_hit(&self) {
+        *self.ponderhit.lock().unwrap() = Some(Instant::now());
+        self.ponder.store(false, Ordering::SeqCst);
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    fn pondering(&self) -> bool {
+        self.ponder.load(Ordering::Relaxed)
+    }
+
+    fn ponderhit_time(&self) -> Option<Instant> {
+        *self.ponderhit.lock().unwrap()
+    }
+}
+
+/// Deepest completed iteration of a search, `eval` is given from the side to move point of view
+#[derive(Clone, Copy, Debug)]
+pub struct SearchResult {
+    pub depth: usize,
+    pub eval: i32,
+    pub best_move: Move,
+    /// Nodes searched by every thread
+    pub nodes: u64,
+    /// Time and nodes searched when an iteration first returned the best move, which no later
+    /// iteration changed
+    pub found_time: Duration,
+    pub found_nodes: u64,
+}
+
+/// Search state of a single ply
+#[derive(Clone, Copy, Debug, Default)]
+struct StackEntry {
+    /// Quiet moves that caused a beta cutoff at this ply
+    killers: [Move; 2],
+    /// Piece and destination of the move played at this ply, `None` for a null move
+    moved: Option<PieceTo>,
+}
+
+/// State shared by every search thread
+struct SharedState<'a> {
+    position: &'a Position,
+    tt: &'a TranspositionTable,
+    network: &'a Network,
+    tablebases: &'a Tablebases,
+    /// Root moves allowed to be searched, restricted by the tablebases when they cover the root
+    root_moves: &'a [Move],
+    timer: &'a TimeManager,
+    signals: &'a SearchSignals,
+    node_counts: &'a [AtomicU64],
+    tb_hits: &'a AtomicU64,
+    node_limit: Option<u64>,
+}
+
+/// Per thread search state, shares the transposition table and signals with the other threads
+struct SearchContext<'a> {
+    id: usize,
+    tt: &'a TranspositionTable,
+    tablebases: &'a Tablebases,
+    root_moves: &'a [Move],
+    timer: &'a TimeManager,
+    signals: &'a SearchSignals,
+    node_counts: &'a [AtomicU64],
+    tb_hits: &'a AtomicU64,
+    node_limit: Option<u64>,
+    options: SearchOptions,
+    nodes: u64,
+    seldepth: usize,
+    stack: Vec<StackEntry>,
+    history: History,
+    /// Hashes of the game positions before the root followed by those of the current search path
+    hashes: Vec<ZHash>,
+    /// Number of game positions in `hashes`, later entries were reached during the search
+    game_ply: usize,
+    accumulators: AccumulatorStack<'a>,
+}
+
+impl<'a> SearchContext<'a> {
+    fn new(id: usize, shared: &SharedState<'a>, options: SearchOptions) -> Self {
+        let position = shared.position;
+
+        Self {
+            id,
+            tt: shared.tt,
+            tablebases: shared.tablebases,
+            root_moves: shared.root_moves,
+            timer: shared.timer,
+            signals: shared.signals,
+            node_counts: shared.node_counts,
+            tb_hits: shared.tb_hits,
+            node_limit: shared.node_limit,
+            options,
+            nodes: 0,
+            seldepth: 0,
+            stack: vec![StackEntry::default(); MAX_PLY + 2],
+            history: History::default(),
+            hashes: position.history.clone(),
+            game_ply: position.history.len(),
+            accumulators: AccumulatorStack::new(shared.network, &position.board),
         }
     }
 
-    moves.sort_by_key(|m| std::cmp::Reverse(move_score(m, board)));
+    fn is_main(&self) -> bool {
+        self.id == 0
+    }
+
+    /// Checks the stop signal, the main thread also raises it once the time or node budget is spent.
+    /// The node limit only counts the nodes of the main thread
+    fn should_stop(&self) -> bool {
+        if self.is_main() && self.node_limit.is_some_and(|limit| self.nodes >= limit) {
+            self.signals.stop.store(true, Ordering::Relaxed);
+        }
 
-    let (tx, rx) = mpsc::channel();
-    let mut handles = vec![];
+        if self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
+            self.publish_nodes();
 
-    for m in moves {
-        let board_clone = *board;
-        let tx_clone = tx.clone();
-        let colour = board.side;
+            if self.is_main()
+                && !self.signals.pondering()
+                && self.timer.hard_expired(self.signals.ponderhit_time())
+            {
+                self.signals.stop.store(true, Ordering::Relaxed);
+            }
+        }
 
-        let handle = thread::spawn(move || {
-            let mut new_board = board_clone;
-            new_board.make_move(m);
-            let eval = negamax(&mut new_board, depth - 1, -INF, INF, !colour);
-            tx_clone.send((eval, m)).unwrap();
-        });
-        handles.push(handle);
+        self.signals.stopped()
     }
 
-    let mut results = vec![];
-    for _ in 0..handles.len() {
-        results.push(rx.recv().unwrap());
+    /// Makes the node count of this thread visible to the main thread
+    fn publish_nodes(&self) {
+        self.node_counts[self.id].store(self.nodes, Ordering::Relaxed);
     }
 
-    for handle in handles {
-        handle.join().unwrap();
+    /// Whether the position repeats one of the search path, or appears twice in the game history.
+    /// Only positions with the same side to move since the last irreversible move are compared
+    fn is_repetition(&self, board: &Board) -> bool {
+        let reversible = (board.halfmoves as usize).min(self.hashes.len());
+        let mut count = 0;
+
+        for i in (self.hashes.len() - reversible..self.hashes.len().saturating_sub(1))
+            .rev()
+            .step_by(2)
+        {
+            if self.hashes[i] == board.hash {
+                if i >= self.game_ply {
+                    return true;
+                }
+
+                count += 1;
+                if count == 2 {
+                    return true;
+                }
+            }
+        }
+
+        false
     }
 
-    if board.side == Colour::White {
-        results.into_iter().max_by_key(|&(eval, _)| eval)
-    } else {
-        results.into_iter().min_by_key(|&(eval, _)| eval)
+    /// Moves played one and two plies before `ply`, used by the continuation history
+    fn previous_moves(&self, ply: usize) -> [Option<PieceTo>; 2] {
+        let moved = |back: usize| ply.checked_sub(back).and_then(|p| self.stack[p].moved);
+        [moved(1), moved(2)]
+    }
+
+    /// Stores a quiet move that caused a beta cutoff and updates the history tables
+    fn update_quiet_stats(
+        &mut self,
+        board: &Board,
+        ply: usize,
+        depth: usize,
+        best: (Move, Piece),
+        tried: &[(Move, Piece)],
+    ) {
+        let killers = &mut self.stack[ply].killers;
+        if killers[0] != best.0 {
+            killers[1] = killers[0];
+            killers[0] = best.0;
+        }
+
+        let previous = self.previous_moves(ply);
+        self.history
+            .update(board.side, depth, best, tried, &previous);
+    }
+
+    fn total_nodes(&self) -> u64 {
+        self.publish_nodes();
+        self.node_counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+/// Lazy SMP search: every thread runs its own iterative deepening over the same root position,
+/// sharing information only through the transposition table. The main thread is in charge of
+/// time management and reporting, helpers are stopped as soon as it finishes.
+///
+/// When the root position is covered by the tablebases only the moves keeping its best result
+/// under the 50-move rule are searched.
+pub fn find_best_move(
+    position: &Position,
+    limits: &SearchLimits,
+    options: &SearchOptions,
+    tt: &TranspositionTable,
+    network: &Network,
+    tablebases: &Tablebases,
+    signals: &SearchSignals,
+) -> SearchResult {
+    let board = &position.board;
+    let timer = TimeManager::new(limits, board.side, options.move_overhead);
+
+    let moves = board.generate_legal_moves();
+    if moves.is_empty() {
+        let in_check = board.is_attacked_by(board.king_square(board.side), !board.side);
+        return SearchResult {
+            depth: 0,
+            eval: if in_check { -MATE } else { DRAW },
+            best_move: Move::default(),
+            nodes: 0,
+            found_time: Duration::ZERO,
+            found_nodes: 0,
+        };
     }
-    .map(|(_, mv)| mv)
-    .unwrap_or(Move::default())
+
+    let root_moves = tablebases
+        .rank_root_moves(board)
+        .unwrap_or_else(|| moves.to_vec());
+    let max_depth = limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+    let threads = options.threads.max(1);
+    let node_counts: Vec<AtomicU64> = (0..threads).map(|_| AtomicU64::new(0)).collect();
+    let tb_hits = AtomicU64::new(0);
+    let shared = SharedState {
+        position,
+        tt,
+        network,
+        tablebases,
+        root_moves: &root_moves,
+        timer: &timer,
+        signals,
+        node_counts: &node_counts,
+        tb_hits: &tb_hits,
+        node_limit: limits.nodes,
+    };
+
+    let results = thread::scope(|s| {
+        let helpers: Vec<_> = (1..threads)
+            .map(|id| {
+                let mut ctx = SearchContext::new(id, &shared, *options);
+                let fallback = root_moves[0];
+                s.spawn(move || iterative_deepening(board, &mut ctx, max_depth, 1, fallback))
+            })
+            .collect();
+
+        let mut ctx = SearchContext::new(0, &shared, *options);
+        let main = iterative_deepening(board, &mut ctx, max_depth, options.multipv, root_moves[0]);
+
+        // UCI forbids reporting the best move of an infinite or ponder search before being told to
+        while (limits.infinite || signals.pondering()) && !signals.stopped() {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        signals.stop.store(true, Ordering::Relaxed);
+
+        let mut results = vec![main];
+        results.extend(helpers.into_iter().map(|handle| handle.join().unwrap()));
+        results
+    });
+
+    // Prefer the deepest completed iteration, ties are resolved by score and then by thread order
+    let mut result = results
+        .into_iter()
+        .reduce(|best, result| {
+            if (result.depth, result.eval) > (best.depth, best.eval) {
+                result
+            } else {
+                best
+            }
+        })
+        .expect("Main thread always returns a result");
+
+    result.nodes = node_counts
+        .iter()
+        .map(|count| count.load(Ordering::Relaxed))
+        .sum();
+    result
 }
 
-fn negamax(board: &mut Board, depth: usize, mut alpha: i32, beta: i32, turn: Colour) -> i32 {
+fn iterative_deepening(
+    board: &Board,
+    ctx: &mut SearchContext,
+    max_depth: usize,
+    multipv: usize,
+    fallback: Move,
+) -> SearchResult {
+    let mut result = SearchResult {
+        depth: 0,
+        eval: -INF,
+        best_move: fallback,
+        nodes: 0,
+        found_time: Duration::ZERO,
+        found_nodes: 0,
+    };
+
+    // Moves are made and unmade on this copy for the whole search
+    let mut board = *board;
+
+    // Helpers start at different depths so that threads desynchronise sooner
+    let start_depth = (1 + ctx.id % 2).min(max_depth);
+
+    'deepening: for depth in start_depth..=max_depth {
+        ctx.seldepth = 0;
+        let mut lines: Vec<(i32, Move)> = Vec::with_capacity(multipv);
+
+        while lines.len() < multipv {
+            let excluded: Vec<Move> = lines.iter().map(|&(_, m)| m).collect();
+            match search_root(&mut board, ctx, depth, &excluded) {
+                Some(line) => lines.push(line),
+                None if ctx.signals.stopped() => break 'deepening,
+                None => break, // Every root move already has its own line
+            }
+        }
+
+        if result.depth == 0 || lines[0].1 != result.best_move {
+            result.found_time = ctx.timer.elapsed();
+            result.found_nodes = ctx.total_nodes();
+        }
+        (result.eval, result.best_move) = lines[0];
+        result.depth = depth;
+
+        if ctx.is_main() {
+            if ctx.options.info {
+                report_iteration(&board, depth, &lines, ctx);
+            }
+
+            if !ctx.signals.pondering() && ctx.timer.soft_expired(ctx.signals.ponderhit_time()) {
+                break;
+            }
+        }
+    }
+
+    ctx.publish_nodes();
+    result
+}
+
+/// Searches the root position skipping the excluded moves, which belong to previous MultiPV
+/// lines. Returns `None` if the search was aborted or there was no move left to search
+fn search_root(
+    board: &mut Board,
+    ctx: &mut SearchContext,
+    depth: usize,
+    excluded: &[Move],
+) -> Option<(i32, Move)> {
+    let tt_move = ctx.tt.probe(board.hash).and_then(|entry| entry.get_move());
+    let mut picker = MovePicker::new(board, tt_move, ctx.stack[0].killers, None);
+    ctx.stack[1].killers = [Move::default(); 2];
+
+    let mut alpha = -INF;
+    let mut best = None;
+    while let Some(m) = picker.next(board, &ctx.history, &[None, None]) {
+        if excluded.contains(&m) || !ctx.root_moves.contains(&m) {
+            continue;
+        }
+
+        ctx.stack[0].moved = board
+            .piece_at(m.get_source())
+            .map(|piece| (piece, m.get_dest()));
+        ctx.hashes.push(board.hash);
+        let undo = board.make_move(m);
+        ctx.accumulators.push(board);
+
+        let mut score = -INF;
+        if best.is_some() {
+            score = -negamax(board, ctx, depth - 1, 1, -alpha - 1, -alpha, true);
+        }
+        if best.is_none() || score > alpha {
+            score = -negamax(board, ctx, depth - 1, 1, -INF, -alpha, true);
+        }
+        ctx.hashes.pop();
+        ctx.accumulators.pop();
+        board.unmake_move(m, undo);
+
+        if ctx.signals.stopped() {
+            return None;
+        }
+
+        if score > alpha {
+            alpha = score;
+            best = Some((score, m));
+        }
+    }
+
+    let (best_eval, best_move) = best?;
+    if excluded.is_empty() {
+        ctx.tt
+            .insert(board.hash, TTFlag::Exact, best_move, 0, best_eval, depth, 0);
+    }
+
+    Some((best_eval, best_move))
+}
+
+/// Principal variation search, `can_null` is false right after a null move so that two of them
+/// are never played in a row
+fn negamax(
+    board: &mut Board,
+    ctx: &mut SearchContext,
+    mut depth: usize,
+    ply: usize,
+    mut alpha: i32,
+    beta: i32,
+    can_null: bool,
+) -> i32 {
+    if ply > 0 && (ctx.is_repetition(board) || board.is_draw()) {
+        return DRAW;
+    }
+
+    let in_check = board.is_attacked_by(board.king_square(board.side), !board.side);
+    if in_check && ctx.options.check_extensions {
+        depth += 1;
+    }
+
     if depth == 0 {
-        return evaluate(board);
+        return quiescence(board, ctx, ply, alpha, beta);
     }
 
-    let mut moves = board.generate_legal_moves();
-    if moves.is_empty() {
-        let king_square = board.king_square(turn);
-        return if board.is_attacked_by(king_square, !turn) {
-            -MATE - depth as i32
+    if ctx.should_stop() {
+        return 0;
+    }
+
+    ctx.nodes += 1;
+    ctx.seldepth = ctx.seldepth.max(ply);
+
+    if ply >= MAX_PLY {
+        return ctx.accumulators.evaluate(board);
+    }
+
+    let pv_node = beta - alpha > 1;
+
+    let tt_entry = ctx.tt.probe(board.hash);
+    if let Some(entry) = tt_entry {
+        if !pv_node && entry.get_depth() >= depth {
+            let value = entry.get_value(ply);
+            let cutoff = match entry.get_flag() {
+                TTFlag::Exact => true,
+                TTFlag::LowerBound => value >= beta,
+                TTFlag::UpperBound => value <= alpha,
+            };
+
+            if cutoff {
+                return value;
+            }
+        }
+    }
+
+    // Right after a capture or pawn move the tablebases know the result of the position
+    if board.halfmoves == 0 && ctx.tablebases.can_probe(board) {
+        if let Some(wdl) = ctx.tablebases.probe_wdl(board) {
+            ctx.tb_hits.fetch_add(1, Ordering::Relaxed);
+
+            let (score, flag) = match wdl {
+                Wdl::Win => (TB_WIN - ply as i32, TTFlag::LowerBound),
+                Wdl::Loss => (-TB_WIN + ply as i32, TTFlag::UpperBound),
+                _ => (DRAW, TTFlag::Exact),
+            };
+            let cutoff = match flag {
+                TTFlag::Exact => true,
+                TTFlag::LowerBound => score >= beta,
+                TTFlag::UpperBound => score <= alpha,
+            };
+
+            if cutoff {
+                ctx.tt
+                    .insert(board.hash, flag, Move::default(), 0, score, depth, ply);
+                return score;
+            }
+        }
+    }
+
+    let static_eval = if in_check {
+        -INF
+    } else {
+        ctx.accumulators.evaluate(board)
+    };
+
+    if !pv_node && !in_check {
+        // Reverse futility pruning: the position is so good that it will most likely fail high
+        if ctx.options.reverse_futility
+            && depth <= RFP_MAX_DEPTH
+            && static_eval - RFP_MARGIN * depth as i32 >= beta
+        {
+            return static_eval;
+        }
+
+        // Razoring: hopeless positions are verified with a quiescence search only
+        if ctx.options.razoring
+            && depth <= RAZORING_MAX_DEPTH
+            && static_eval + RAZORING_MARGIN * (depth as i32) < alpha
+        {
+            let score = quiescence(board, ctx, ply, alpha, alpha + 1);
+            if score <= alpha {
+                return score;
+            }
+        }
+
+        // Null move pruning: passing the turn still fails high, disabled without pieces to
+        // avoid zugzwang positions
+        if ctx.options.null_move
+            && can_null
+            && depth >= NULL_MOVE_MIN_DEPTH
+            && static_eval >= beta
+            && board.has_non_pawn_material(board.side)
+        {
+            let reduction = 3 + depth / 4;
+            ctx.stack[ply].moved = None;
+            ctx.hashes.push(board.hash);
+            let undo = board.make_null_move();
+            let score = -negamax(
+                board,
+                ctx,
+                depth.saturating_sub(reduction + 1),
+                ply + 1,
+                -beta,
+                -beta + 1,
+                false,
+            );
+            ctx.hashes.pop();
+            board.unmake_null_move(undo);
+
+            if ctx.signals.stopped() {
+                return 0;
+            }
+
+            if score >= beta {
+                return if score >= MATE_BOUND { beta } else { score };
+            }
+        }
+    }
+
+    let tt_move = tt_entry.and_then(|entry| entry.get_move());
+    let previous = ctx.previous_moves(ply);
+    let countermove = ctx.history.countermove(previous[0]);
+    let mut picker = MovePicker::new(board, tt_move, ctx.stack[ply].killers, countermove);
+    if picker.is_empty(board) {
+        return if in_check {
+            -MATE + ply as i32
         } else {
-            0 // Draw
+            DRAW // Stalemate
         };
     }
 
-    moves.sort_by_key(|m| std::cmp::Reverse(move_score(m, board)));
+    ctx.stack[ply + 1].killers = [Move::default(); 2];
 
+    let original_alpha = alpha;
     let mut max_score = -INF;
-    for m in moves {
-        let mut new_board = *board;
-        new_board.make_move(m);
-        let score = -negamax(&mut new_board, depth - 1, -beta, -alpha, !turn);
+    let mut best_move = Move::default();
+    let mut quiets_tried: Vec<(Move, Piece)> = Vec::new();
+    let hash = board.hash;
+    let mut i = 0;
+    while let Some(m) = picker.next(board, &ctx.history, &previous) {
+        let is_quiet = !is_tactical(m);
+        let piece = board
+            .piece_at(m.get_source())
+            .expect("No piece in source square");
+
+        let undo = board.make_move(m);
+        let gives_check = board.is_attacked_by(board.king_square(board.side), !board.side);
+
+        // Futility pruning: quiet moves can not raise a far away alpha at low depths
+        if ctx.options.futility
+            && !pv_node
+            && !in_check
+            && !gives_check
+            && is_quiet
+            && i > 0
+            && depth <= FUTILITY_MAX_DEPTH
+            && max_score > -MATE_BOUND
+            && static_eval + FUTILITY_MARGIN * (depth as i32) <= alpha
+        {
+            board.unmake_move(m, undo);
+            i += 1;
+            continue;
+        }
+
+        ctx.stack[ply].moved = Some((piece, m.get_dest()));
+        ctx.hashes.push(hash);
+        ctx.accumulators.push(board);
+
+        let score = if i == 0 {
+            -negamax(board, ctx, depth - 1, ply + 1, -beta, -alpha, true)
+        } else {
+            // Late move reductions: quiet moves ordered last are searched shallower first
+            let reduction = if ctx.options.lmr
+                && depth >= LMR_MIN_DEPTH
+                && i >= LMR_MIN_MOVES
+                && is_quiet
+                && !in_check
+                && !gives_check
+            {
+                lmr_reduction(depth, i).min(depth - 1)
+            } else {
+                0
+            };
+
+            let mut score = -negamax(
+                board,
+                ctx,
+                depth - 1 - reduction,
+                ply + 1,
+                -alpha - 1,
+                -alpha,
+                true,
+            );
+
+            if score > alpha && reduction > 0 {
+                score = -negamax(board, ctx, depth - 1, ply + 1, -alpha - 1, -alpha, true);
+            }
+
+            if score > alpha && score < beta {
+                score = -negamax(board, ctx, depth - 1, ply + 1, -beta, -alpha, true);
+            }
+
+            score
+        };
+        ctx.hashes.pop();
+        ctx.accumulators.pop();
+        board.unmake_move(m, undo);
 
         if score > max_score {
             max_score = score;
+            best_move = m;
         }
 
         if score > alpha {
@@ -90,37 +724,247 @@ fn negamax(board: &mut Board, depth: usize, mut alpha: i32, beta: i32, turn: Col
         }
 
         if alpha >= beta {
+            if is_quiet {
+                ctx.update_quiet_stats(board, ply, depth, (m, piece), &quiets_tried);
+            }
             break; // Beta cutoff
         }
+
+        if is_quiet {
+            quiets_tried.push((m, piece));
+        }
+        i += 1;
     }
 
+    if ctx.signals.stopped() {
+        return 0;
+    }
+
+    let flag = if max_score >= beta {
+        TTFlag::LowerBound
+    } else if max_score > original_alpha {
+        TTFlag::Exact
+    } else {
+        TTFlag::UpperBound
+    };
+
+    ctx.tt.insert(
+        board.hash,
+        flag,
+        best_move,
+        static_eval,
+        max_score,
+        depth,
+        ply,
+    );
+
     max_score
 }
 
-fn move_score(m: &Move, board: &Board) -> i32 {
-    let mut score = 0;
+fn lmr_reduction(depth: usize, move_number: usize) -> usize {
+    (0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25) as usize
+}
+
+/// Searches captures and promotions until the position is quiet, so that the static evaluation
+/// is never taken in the middle of an exchange. When in check every evasion is searched instead.
+fn quiescence(
+    board: &mut Board,
+    ctx: &mut SearchContext,
+    ply: usize,
+    mut alpha: i32,
+    beta: i32,
+) -> i32 {
+    if ctx.should_stop() {
+        return 0;
+    }
+
+    ctx.nodes += 1;
+    ctx.seldepth = ctx.seldepth.max(ply);
+
+    if ply >= MAX_PLY {
+        return ctx.accumulators.evaluate(board);
+    }
+
+    let in_check = board.is_attacked_by(board.king_square(board.side), !board.side);
+
+    // Losing captures are left to the main search, unless every evasion has to be tried
+    let mut picker = if in_check {
+        MovePicker::new(board, None, [Move::default(); 2], None)
+    } else {
+        MovePicker::new_qsearch(board)
+    };
+
+    if in_check && picker.is_empty(board) {
+        return -MATE + ply as i32;
+    }
+
+    let stand_pat = if in_check {
+        -INF
+    } else {
+        ctx.accumulators.evaluate(board)
+    };
+    if stand_pat >= beta {
+        return stand_pat;
+    }
+    alpha = alpha.max(stand_pat);
+
+    let mut best_score = stand_pat;
+    while let Some(m) = picker.next(board, &ctx.history, &[None, None]) {
+        if !in_check && !m.get_type().is_promotion() {
+            // Delta pruning: not even winning the piece for free can raise alpha
+            let captured = board
+                .piece_at(m.get_dest())
+                .map_or(PIECE_VALUES[0], |piece| PIECE_VALUES[piece.index()]);
+            if stand_pat + captured + DELTA_MARGIN <= alpha {
+                continue;
+            }
+        }
+
+        let undo = board.make_move(m);
+        ctx.accumulators.push(board);
+        let score = -quiescence(board, ctx, ply + 1, -beta, -alpha);
+        ctx.accumulators.pop();
+        board.unmake_move(m, undo);
+
+        if score > best_score {
+            best_score = score;
+
+            if score > alpha {
+                alpha = score;
+            }
+
+            if alpha >= beta {
+                break;
+            }
+        }
+    }
+
+    best_score
+}
+
+fn report_iteration(board: &Board, depth: usize, lines: &[(i32, Move)], ctx: &SearchContext) {
+    let nodes = ctx.total_nodes();
+    let elapsed = ctx.timer.elapsed().as_millis() as u64;
+    let nps = nodes * 1000 / elapsed.max(1);
+    let hashfull = ctx.tt.hashfull();
+    let tb_hits = ctx.tb_hits.load(Ordering::Relaxed);
+
+    for (i, &(eval, m)) in lines.iter().enumerate() {
+        let pv: Vec<String> = extract_pv(board, m, ctx.tt, depth)
+            .iter()
+            .map(|m| m.to_uci(ctx.options.chess960))
+            .collect();
+
+        println!(
+            "info depth {} seldepth {} multipv {} score {} nodes {} nps {} hashfull {} tbhits {} time {} pv {}",
+            depth,
+            ctx.seldepth,
+            i + 1,
+            format_score(eval),
+            nodes,
+            nps,
+            hashfull,
+            tb_hits,
+            elapsed,
+            pv.join(" ")
+        );
+    }
+}
 
-    if m.get_type().is_capture() {
-        let src_piece = board.piece_at(m.get_source()).unwrap();
-        let dest_piece = board.piece_at(m.get_dest());
+/// Follows the best moves stored in the transposition table, starting from the root best move
+fn extract_pv(board: &Board, best_move: Move, tt: &TranspositionTable, depth: usize) -> Vec<Move> {
+    let mut pv = vec![best_move];
+    let mut board = *board;
+    board.make_move(best_move);
 
-        if let Some(dest_piece) = dest_piece {
-            score += 10 * PIECE_VALUES[dest_piece.index()] - PIECE_VALUES[src_piece.index()];
-        } else if m.get_type() == MoveKind::EnPassant {
-            score += PIECE_VALUES[0];
+    while pv.len() < depth {
+        let Some(m) = tt.probe(board.hash).and_then(|entry| entry.get_move()) else {
+            break;
+        };
+
+        if !board.generate_legal_moves().contains(&m) {
+            break;
         }
+
+        board.make_move(m);
+        pv.push(m);
     }
 
-    if m.get_type().is_promotion() {
-        let promo_piece = m.get_type().get_promotion(board.side);
-        score += PIECE_VALUES[promo_piece.index()];
+    pv
+}
+
+pub fn format_score(eval: i32) -> String {
+    if eval.abs() >= MATE_BOUND {
+        let moves = (MATE - eval.abs() + 1) / 2;
+        format!("mate {}", if eval > 0 { moves } else { -moves })
+    } else {
+        format!("cp {}", eval)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::piece::Colour;
 
-    let mut new_board = *board;
-    new_board.make_move(*m);
-    if new_board.is_attacked_by(new_board.king_square(!board.side), board.side) {
-        score += 50;
+    fn play(board: &Board, uci: &str) -> Move {
+        board
+            .generate_legal_moves()
+            .into_iter()
+            .find(|m| m.to_string() == uci)
+            .unwrap()
     }
 
-    score
+    #[test]
+    fn test_repetition() {
+        const SHUFFLE: [&str; 4] = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+        // The root follows a first knight shuffle
+        let mut position = Position {
+            board: Board::default(),
+            history: Vec::new(),
+        };
+        for uci in SHUFFLE {
+            position.make_move(play(&position.board, uci));
+        }
+
+        let tt = TranspositionTable::new(1);
+        let network = Network::embedded();
+        let tablebases = Tablebases::default();
+        let root_moves = position.board.generate_legal_moves().to_vec();
+        let timer = TimeManager::new(&SearchLimits::default(), Colour::White, 0);
+        let signals = SearchSignals::default();
+        let node_counts = [AtomicU64::new(0)];
+        let tb_hits = AtomicU64::new(0);
+        let shared = SharedState {
+            position: &position,
+            tt: &tt,
+            network: &network,
+            tablebases: &tablebases,
+            root_moves: &root_moves,
+            timer: &timer,
+            signals: &signals,
+            node_counts: &node_counts,
+            tb_hits: &tb_hits,
+            node_limit: None,
+        };
+        let mut ctx = SearchContext::new(0, &shared, SearchOptions::default());
+        let mut board = position.board;
+
+        // A single earlier occurrence in the game is not enough
+        assert!(!ctx.is_repetition(&board));
+
+        // Shuffling again inside the search repeats the root, which is scored as a draw
+        for (i, uci) in SHUFFLE.into_iter().enumerate() {
+            let m = play(&board, uci);
+            ctx.hashes.push(board.hash);
+            board.make_move(m);
+            ctx.accumulators.push(&board);
+            assert_eq!(ctx.is_repetition(&board), i == SHUFFLE.len() - 1, "{}", uci);
+        }
+        assert_eq!(
+            negamax(&mut board, &mut ctx, 4, SHUFFLE.len(), -INF, INF, true),
+            DRAW
+        );
+    }
 }
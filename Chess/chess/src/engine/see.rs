@@ -0,0 +1,169 @@
+use crate::engine::evaluation::PIECE_VALUES;
+use crate::game::{
+    bitboard::BitBoard,
+    board::Board,
+    magic::{bishop_attacks, rook_attacks},
+    moves::{Move, MoveKind},
+    piece::Piece,
+};
+
+/// Piece types ordered from least to most valuable, used to pick the next attacker
+const ATTACKER_ORDER: [Piece; 6] = [
+    Piece::WP,
+    Piece::WN,
+    Piece::WB,
+    Piece::WR,
+    Piece::WQ,
+    Piece::WK,
+];
+
+/// Static Exchange Evaluation: returns whether the sequence of captures started by `m` on its
+/// destination square wins at least `threshold` centipawns for the side to move, assuming both
+/// sides always recapture with their least valuable attacker and may stop at any point.
+pub fn see(board: &Board, m: Move, threshold: i32) -> bool {
+    let (src, dest) = (m.get_source(), m.get_dest());
+    let move_type = m.get_type();
+
+    if move_type == MoveKind::Castle || move_type.is_promotion() {
+        return threshold <= 0;
+    }
+
+    let captured = match move_type {
+        MoveKind::EnPassant => PIECE_VALUES[Piece::WP.index()],
+        _ => board
+            .piece_at(dest)
+            .map_or(0, |piece| PIECE_VALUES[piece.index()]),
+    };
+
+    // Balance after our capture, if it is already below the threshold the exchange is lost
+    let mut swap = captured - threshold;
+    if swap < 0 {
+        return false;
+    }
+
+    // Balance if the opponent recaptures our piece, if it still passes the threshold it is won
+    let moving = board.piece_at(src).expect("No piece in source square");
+    swap = PIECE_VALUES[moving.index()] - swap;
+    if swap <= 0 {
+        return true;
+    }
+
+    let mut occupied = board.sides[0] | board.sides[1];
+    occupied = occupied.pop_bit(src).set_bit(dest);
+    if move_type == MoveKind::EnPassant {
+        let captured_square = dest
+            .jump(0, -moving.colour().forward())
+            .expect("Off the board en_passant");
+        occupied = occupied.pop_bit(captured_square);
+    }
+
+    let bishops = board.pieces[Piece::WB.index()] | board.pieces[Piece::WQ.index()];
+    let rooks = board.pieces[Piece::WR.index()] | board.pieces[Piece::WQ.index()];
+
+    let mut attackers = board.attackers_to(dest, occupied);
+    let mut side = board.side;
+    let mut result = true;
+
+    loop {
+        side = !side;
+        attackers &= occupied;
+
+        let side_attackers = attackers & board.sides[side as usize];
+        if side_attackers == BitBoard::EMPTY {
+            break;
+        }
+
+        result = !result;
+
+        let (piece, square) = ATTACKER_ORDER
+            .iter()
+            .find_map(|&piece| {
+                let bb = side_attackers & board.pieces[piece.index()];
+                (bb != BitBoard::EMPTY).then(|| (piece, bb.lsb()))
+            })
+            .expect("Attacker without piece type");
+
+        // The king can only capture if the opponent has no attackers left
+        if piece == Piece::WK {
+            let opponent_attackers = attackers & board.sides[!side as usize];
+            return if opponent_attackers != BitBoard::EMPTY {
+                !result
+            } else {
+                result
+            };
+        }
+
+        swap = PIECE_VALUES[piece.index()] - swap;
+        if swap < result as i32 {
+            break;
+        }
+
+        occupied = occupied.pop_bit(square);
+
+        // Removing the attacker may reveal sliders behind it
+        if matches!(piece, Piece::WP | Piece::WB | Piece::WQ) {
+            attackers |= bishop_attacks(occupied.0, dest.index()) & bishops;
+        }
+        if matches!(piece, Piece::WR | Piece::WQ) {
+            attackers |= rook_attacks(occupied.0, dest.index()) & rooks;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::square::Square;
+
+    fn capture(board: &Board, src: &str, dest: &str) -> Move {
+        board
+            .generate_legal_moves()
+            .into_iter()
+            .find(|m| m.get_source() == Square::from(src) && m.get_dest() == Square::from(dest))
+            .expect("Move not legal in test position")
+    }
+
+    #[test]
+    fn test_see() {
+        // (FEN, source, destination, material won by the exchange)
+        const SEE_SUITE: [(&str, &str, &str, i32); 8] = [
+            // Undefended pawn
+            ("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1", "d5", 100),
+            // Pawn defended by a pawn, rook is lost
+            ("4k3/8/4p3/3p4/8/8/8/3RK3 w - - 0 1", "d1", "d5", 100 - 500),
+            // Knight takes pawn defended by a pawn
+            ("4k3/8/2p5/3p4/8/2N5/8/4K3 w - - 0 1", "c3", "d5", 100 - 320),
+            // Pawn takes defended knight
+            ("4k3/8/2p5/3n4/4P3/8/8/4K3 w - - 0 1", "e4", "d5", 320 - 100),
+            // Doubled rooks against a single defender, the x-ray recapture wins the pawn
+            ("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2", "d5", 100),
+            // Doubled rooks against doubled rooks
+            (
+                "3rk3/3r4/8/3p4/8/8/3R4/3RK3 w - - 0 1",
+                "d2",
+                "d5",
+                100 - 500,
+            ),
+            // Queen takes pawn defended by the king only
+            ("8/8/8/3pk3/8/8/8/3QK3 w - - 0 1", "d1", "d5", 100 - 900),
+            // The king can not recapture while the queen x-rays the square
+            ("8/8/8/3pk3/8/8/3R4/3QK3 w - - 0 1", "d2", "d5", 100),
+        ];
+
+        for (fen, src, dest, value) in SEE_SUITE {
+            let board = Board::from_fen(fen).unwrap();
+            let m = capture(&board, src, dest);
+            assert!(
+                see(&board, m, value),
+                "{fen}: {src}{dest} should win {value}"
+            );
+            assert!(
+                !see(&board, m, value + 1),
+                "{fen}: {src}{dest} should not win {}",
+                value + 1
+            );
+        }
+    }
+}
@@ -0,0 +1,1822 @@
+use crate::game::{
+    board::Board,
+    constants::KING_ATTACKS,
+    moves::Move,
+    piece::{Colour, Piece},
+    square::Square,
+};
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Largest number of pieces a Syzygy table can hold
+const TB_PIECES: usize = 7;
+
+/// Root moves winning within the 50-move rule are ranked with this value
+const MAX_DTZ: i32 = 1 << 18;
+
+#[cfg(windows)]
+const PATH_SEPARATOR: char = ';';
+#[cfg(not(windows))]
+const PATH_SEPARATOR: char = ':';
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+/// Flags of the per-file subtables
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// Piece types in the order they are written in table names
+const NAME_ORDER: [(char, usize); 6] = [('K', 5), ('Q', 4), ('R', 3), ('B', 2), ('N', 1), ('P', 0)];
+
+/// Game theoretical value of a position, from the side to move point of view. Cursed wins and
+/// blessed losses are decisive results that the 50-move rule turns into draws
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Option<Self> {
+        match value {
+            -2 => Some(Self::Loss),
+            -1 => Some(Self::BlessedLoss),
+            0 => Some(Self::Draw),
+            1 => Some(Self::CursedWin),
+            2 => Some(Self::Win),
+            _ => None,
+        }
+    }
+
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+
+    /// Distance to zeroing of a position whose best move is a capture or a pawn move
+    fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Self::Win => 1,
+            Self::CursedWin => 101,
+            Self::Draw => 0,
+            Self::BlessedLoss => -101,
+            Self::Loss => -1,
+        }
+    }
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::from_value(-(self as i32)).expect("Negated result is in range")
+    }
+}
+
+/// Extra outcome of probing a position, besides its value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ProbeState {
+    Ok,
+    /// The best move is a capture or pawn move, DTZ tables hold no useful value for the position
+    ZeroingBestMove,
+}
+
+/// Value read from a table, DTZ tables only store one side to move
+enum TableValue {
+    Value(i32),
+    ChangeStm,
+}
+
+/// Piece counts indexed by colour and piece type, colour 0 being the side written first in the
+/// table name
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Material([[u8; 6]; 2]);
+
+impl Material {
+    fn from_board(board: &Board) -> Self {
+        let mut counts = [[0; 6]; 2];
+        for (colour, side) in counts.iter_mut().enumerate() {
+            for (piece, count) in side.iter_mut().enumerate() {
+                *count = (board.pieces[piece] & board.sides[colour]).count_bits() as u8;
+            }
+        }
+
+        Self(counts)
+    }
+
+    /// Parses a table name such as `KRPvKR`
+    fn from_name(name: &str) -> Option<Self> {
+        let (first, second) = name.split_once('v')?;
+        let mut counts = [[0; 6]; 2];
+
+        for (side, pieces) in [first, second].into_iter().enumerate() {
+            if !pieces.starts_with('K') {
+                return None;
+            }
+
+            for ch in pieces.chars() {
+                let &(_, piece) = NAME_ORDER.iter().find(|&&(c, _)| c == ch)?;
+                counts[side][piece] += 1;
+            }
+        }
+
+        let material = Self(counts);
+        (counts[0][5] == 1 && counts[1][5] == 1 && material.piece_count() <= TB_PIECES)
+            .then_some(material)
+    }
+
+    fn swapped(self) -> Self {
+        Self([self.0[1], self.0[0]])
+    }
+
+    fn piece_count(&self) -> usize {
+        self.0.iter().flatten().map(|&count| count as usize).sum()
+    }
+
+    fn pawns(&self, side: usize) -> usize {
+        self.0[side][0] as usize
+    }
+
+    /// Compact key identifying the material, with a nibble per piece count
+    fn key(&self) -> u64 {
+        self.0
+            .iter()
+            .flatten()
+            .fold(0, |key, &count| key << 4 | count as u64)
+    }
+}
+
+/// Lookup tables used to turn piece placements into table indices, shared by every table
+struct Encoding {
+    /// Number of ways to choose `k` squares out of `n`, indexed as `[k][n]`
+    binomial: [[u64; 64]; TB_PIECES + 1],
+    /// Pawn squares a2-h7 mapped to 0..47, the leading pawn has the highest value
+    map_pawns: [usize; 64],
+    lead_pawn_idx: [[u64; 64]; TB_PIECES],
+    lead_pawns_size: [[u64; 4]; TB_PIECES],
+    /// Squares below the a1-h8 diagonal mapped to 0..27
+    map_b1h1h7: [usize; 64],
+    /// Squares of the a1-d1-d4 triangle mapped to 0..9, diagonal squares last
+    map_a1d1d4: [usize; 64],
+    /// The 462 legal placements of two kings, the first one in the a1-d1-d4 triangle
+    map_kk: [[u64; 64]; 10],
+}
+
+impl Encoding {
+    fn get() -> &'static Self {
+        static ENCODING: OnceLock<Encoding> = OnceLock::new();
+        ENCODING.get_or_init(Self::new)
+    }
+
+    fn new() -> Self {
+        let mut encoding = Self {
+            binomial: [[0; 64]; TB_PIECES + 1],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; TB_PIECES],
+            lead_pawns_size: [[0; 4]; TB_PIECES],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+        };
+
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_diagonal(sq) < 0 {
+                encoding.map_b1h1h7[sq] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = Vec::new();
+        code = 0;
+        for sq in [0, 1, 2, 3, 8, 9, 10, 11, 16, 17, 18, 19, 24, 25, 26, 27] {
+            if off_diagonal(sq) < 0 {
+                encoding.map_a1d1d4[sq] = code;
+                code += 1;
+            } else if off_diagonal(sq) == 0 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            encoding.map_a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        // With the first king on the diagonal the second one is kept on or below it
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for idx in 0..10 {
+            for (s1, attacks) in KING_ATTACKS.iter().enumerate().take(28) {
+                if encoding.map_a1d1d4[s1] != idx || (idx == 0 && s1 != 1) {
+                    continue;
+                }
+
+                for s2 in 0..64 {
+                    let touching = (attacks.0 | 1 << s1) & 1 << s2 != 0;
+                    if touching || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                        continue;
+                    } else if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        encoding.map_kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            encoding.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..=TB_PIECES.min(n) {
+                encoding.binomial[k][n] = if k > 0 {
+                    encoding.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n {
+                    encoding.binomial[k][n - 1]
+                } else {
+                    0
+                };
+            }
+        }
+
+        // The leading pawn is the one closest to the edge and then to the first rank, any
+        // other pawn of its group has a lower value
+        let mut available = 47;
+        for lead in 1..TB_PIECES - 1 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let sq = rank * 8 + file;
+                    if lead == 1 {
+                        encoding.map_pawns[sq] = available;
+                        encoding.map_pawns[sq ^ 7] = available.saturating_sub(1);
+                        available = available.saturating_sub(2);
+                    }
+                    encoding.lead_pawn_idx[lead][sq] = idx;
+                    idx += encoding.binomial[lead - 1][encoding.map_pawns[sq]];
+                }
+                encoding.lead_pawns_size[lead][file] = idx;
+            }
+        }
+
+        encoding
+    }
+}
+
+/// Rank minus file, negative below the a1-h8 diagonal
+fn off_diagonal(sq: usize) -> i32 {
+    (sq / 8) as i32 - (sq % 8) as i32
+}
+
+/// Piece code used by the tables: colour in the fourth bit and piece type from 1 to 6
+fn piece_code(piece: Piece) -> u8 {
+    (piece.colour() as u8) << 3 | (piece.index() as u8 + 1)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32_be(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64_be(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Left and right children of a symbol of the pairing tree, packed in 3 bytes
+fn symbol_pair(data: &[u8], btree: usize, sym: usize) -> Option<(usize, usize)> {
+    let lr = data.get(btree + 3 * sym..btree + 3 * sym + 3)?;
+    let left = ((lr[1] as usize & 0xF) << 8) | lr[0] as usize;
+    let right = ((lr[2] as usize) << 4) | (lr[1] as usize >> 4);
+    Some((left, right))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+/// Compressed values of one side to move and leading file. Values are stored as canonical
+/// Huffman codes of symbols, each symbol expanding recursively into a pair of symbols
+#[derive(Clone, Debug, Default)]
+struct PairsData {
+    flags: u8,
+    pieces: [u8; TB_PIECES],
+    group_len: [usize; TB_PIECES + 1],
+    group_idx: [u64; TB_PIECES + 1],
+    block_size: usize,
+    span: u64,
+    blocks: usize,
+    min_sym_len: u8,
+    /// Offsets of the different sections within the file
+    sparse_index: usize,
+    sparse_index_size: usize,
+    block_lengths: usize,
+    block_lengths_size: usize,
+    lowest_sym: usize,
+    btree: usize,
+    data: usize,
+    /// Lowest code of every symbol length, left aligned to 64 bits
+    base64: Vec<u64>,
+    /// Number of values each symbol expands to, minus one
+    symlen: Vec<u8>,
+    /// Offsets of the four DTZ value maps, relative to the map start
+    map_idx: [usize; 4],
+}
+
+/// A WDL or DTZ table file mapped into memory
+struct Table {
+    data: Mmap,
+    kind: TableKind,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    symmetric: bool,
+    piece_count: usize,
+    /// Pawns of the leading colour followed by those of the other one
+    pawn_count: [usize; 2],
+    /// Indexed by side to move and leading file
+    pairs: [[PairsData; 4]; 2],
+    /// Offset of the DTZ value maps
+    map: usize,
+}
+
+impl Table {
+    fn open(path: &Path, material: Material, kind: TableKind) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        // SAFETY: table files are only read, and are not expected to change while mapped
+        let data = unsafe { Mmap::map(&file) }.ok()?;
+
+        // Both sides having pawns, the leading colour is the one with fewer of them
+        let (white, black) = (material.pawns(0), material.pawns(1));
+        let lead = usize::from(black > 0 && (white == 0 || black < white));
+        let mut table = Self {
+            data,
+            kind,
+            has_pawns: material.pawns(0) + material.pawns(1) > 0,
+            has_unique_pieces: material.0.iter().any(|side| side[..5].contains(&1)),
+            symmetric: material == material.swapped(),
+            piece_count: material.piece_count(),
+            pawn_count: [material.pawns(lead), material.pawns(1 - lead)],
+            pairs: Default::default(),
+            map: 0,
+        };
+
+        (table.pairs, table.map) = table.parse()?;
+        Some(table)
+    }
+
+    fn sides(&self) -> usize {
+        match self.kind {
+            TableKind::Wdl if !self.symmetric => 2,
+            _ => 1,
+        }
+    }
+
+    fn files(&self) -> usize {
+        if self.has_pawns {
+            4
+        } else {
+            1
+        }
+    }
+
+    fn get(&self, stm: usize, file: usize) -> &PairsData {
+        let sides = match self.kind {
+            TableKind::Wdl => 2,
+            TableKind::Dtz => 1,
+        };
+        &self.pairs[stm % sides][if self.has_pawns { file } else { 0 }]
+    }
+
+    /// Reads the headers of every subtable, returned with the offset of the DTZ value maps.
+    /// The compressed values stay in the file
+    #[allow(clippy::needless_range_loop)]
+    fn parse(&self) -> Option<([[PairsData; 4]; 2], usize)> {
+        let data = &self.data[..];
+        let magic = match self.kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if data.get(..4)? != magic {
+            return None;
+        }
+
+        let flags = *data.get(4)?;
+        if (flags & 2 != 0) != self.has_pawns
+            || (self.kind == TableKind::Wdl && (flags & 1 != 0) == self.symmetric)
+        {
+            return None;
+        }
+
+        let mut pairs: [[PairsData; 4]; 2] = Default::default();
+        let mut map = 0;
+        let mut offset = 5;
+        let (sides, files) = (self.sides(), self.files());
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+
+        for file in 0..files {
+            let first = *data.get(offset)?;
+            let second = if both_pawns {
+                *data.get(offset + 1)?
+            } else {
+                0xFF
+            };
+            let order = [
+                [first & 0xF, second & 0xF].map(usize::from),
+                [first >> 4, second >> 4].map(usize::from),
+            ];
+            offset += 1 + usize::from(both_pawns);
+
+            for k in 0..self.piece_count {
+                let byte = *data.get(offset)?;
+                pairs[0][file].pieces[k] = byte & 0xF;
+                pairs[1][file].pieces[k] = byte >> 4;
+                offset += 1;
+            }
+
+            for (side, order) in order.iter().enumerate().take(sides) {
+                self.set_groups(&mut pairs[side][file], file, order);
+            }
+        }
+
+        offset += offset & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                offset = set_sizes(&mut pairs[side][file], data, offset)?;
+            }
+        }
+
+        if self.kind == TableKind::Dtz {
+            map = offset;
+            for file in 0..files {
+                let d = &mut pairs[0][file];
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+
+                if d.flags & FLAG_WIDE != 0 {
+                    offset += offset & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = (offset - map) / 2 + 1;
+                        offset += 2 * read_u16(data, offset)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = offset - map + 1;
+                        offset += *data.get(offset)? as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut pairs[side][file];
+                d.sparse_index = offset;
+                offset += d.sparse_index_size * 6;
+            }
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut pairs[side][file];
+                d.block_lengths = offset;
+                offset += d.block_lengths_size * 2;
+            }
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut pairs[side][file];
+                offset = (offset + 0x3F) & !0x3F;
+                d.data = offset;
+                offset += d.blocks * d.block_size;
+            }
+        }
+
+        (offset <= data.len()).then_some((pairs, map))
+    }
+
+    /// Splits the pieces in groups of identical pieces, each group being encoded as a
+    /// combination of squares. `order` gives the position of the leading group and of the
+    /// remaining pawns in the final index
+    fn set_groups(&self, d: &mut PairsData, file: usize, order: &[usize; 2]) {
+        let encoding = Encoding::get();
+        let (has_pawns, unique) = (self.has_pawns, self.has_unique_pieces);
+        let both_pawns = has_pawns && self.pawn_count[1] > 0;
+
+        let mut first_len: i32 = if has_pawns {
+            0
+        } else if unique {
+            3
+        } else {
+            2
+        };
+        let mut n = 0;
+        d.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+        let mut idx = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                d.group_idx[0] = idx;
+                idx *= if has_pawns {
+                    encoding.lead_pawns_size[d.group_len[0]][file]
+                } else if unique {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                d.group_idx[1] = idx;
+                idx *= encoding.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= encoding.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+
+    /// Looks up the position, `flip` swapping colours when the table stores the other side
+    /// as the stronger one. WDL values are returned as -2..=2 and DTZ ones in plies
+    fn probe(&self, board: &Board, flip: bool, wdl: Wdl) -> Option<TableValue> {
+        let (stm, file, idx) = self.index(board, flip)?;
+
+        if self.kind == TableKind::Dtz {
+            let stored = self.get(0, file).flags & FLAG_STM;
+            if stored as usize != stm && (self.has_pawns || !self.symmetric) {
+                return Some(TableValue::ChangeStm);
+            }
+        }
+
+        let value = self.decompress(self.get(stm, file), idx)? as i32;
+        Some(TableValue::Value(match self.kind {
+            TableKind::Wdl => value - 2,
+            TableKind::Dtz => self.map_dtz(file, value, wdl)?,
+        }))
+    }
+
+    /// Side to move and leading file selecting the subtable of the position, along with the
+    /// index of the position within it
+    fn index(&self, board: &Board, flip: bool) -> Option<(usize, usize, u64)> {
+        let encoding = Encoding::get();
+        let flip_colour = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = usize::from(flip) ^ board.side as usize;
+
+        let mut squares = [0usize; TB_PIECES];
+        let mut pieces = [0u8; TB_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0;
+        let mut file = 0;
+
+        // Pawn tables are split by the file of the leading pawn, which comes first
+        if self.has_pawns {
+            let lead_colour = ((self.get(0, 0).pieces[0] ^ flip_colour) >> 3) as usize;
+            lead_pawns = (board.pieces[0] & board.sides[lead_colour]).0;
+
+            let mut bits = lead_pawns;
+            while bits != 0 {
+                squares[size] = bits.trailing_zeros() as usize ^ flip_squares;
+                size += 1;
+                bits &= bits - 1;
+            }
+
+            let lead = (0..size).max_by_key(|&i| encoding.map_pawns[squares[i]])?;
+            squares.swap(0, lead);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+        let lead_count = size;
+
+        let mut bits = (board.sides[0] | board.sides[1]).0 ^ lead_pawns;
+        while bits != 0 {
+            let sq = bits.trailing_zeros() as usize;
+            let piece = board.piece_at(Square::new(sq))?;
+            *squares.get_mut(size)? = sq ^ flip_squares;
+            pieces[size] = piece_code(piece) ^ flip_colour;
+            size += 1;
+            bits &= bits - 1;
+        }
+
+        let d = self.get(stm, file);
+
+        // Pieces are sorted in the sequence the table was compressed with
+        for i in lead_count..size - 1 {
+            if let Some(j) = (i + 1..size).find(|&j| d.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // The leading piece is mirrored into the a-d files
+        if squares[0] % 8 > 3 {
+            squares[..size].iter_mut().for_each(|sq| *sq ^= 7);
+        }
+
+        let mut idx = if self.has_pawns {
+            let mut idx = encoding.lead_pawn_idx[lead_count][squares[0]];
+            squares[1..lead_count].sort_by_key(|&sq| encoding.map_pawns[sq]);
+            for (i, &sq) in squares.iter().enumerate().take(lead_count).skip(1) {
+                idx += encoding.binomial[i][encoding.map_pawns[sq]];
+            }
+            idx
+        } else {
+            // Without pawns the leading piece is also mirrored below the fifth rank, and the
+            // first piece of the leading group off the diagonal below the a1-h8 diagonal
+            if squares[0] / 8 > 3 {
+                squares[..size].iter_mut().for_each(|sq| *sq ^= 56);
+            }
+
+            if let Some(i) = (0..d.group_len[0]).find(|&i| off_diagonal(squares[i]) != 0) {
+                if off_diagonal(squares[i]) > 0 {
+                    squares[i..size]
+                        .iter_mut()
+                        .for_each(|sq| *sq = ((*sq >> 3) | (*sq << 3)) & 63);
+                }
+            }
+
+            if self.has_unique_pieces {
+                self.encode_unique(&squares)
+            } else {
+                encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]]
+            }
+        };
+
+        idx *= d.group_idx[0];
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort_unstable();
+
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| sq > s).count();
+                n += encoding.binomial[i + 1][sq - adjust - 8 * usize::from(remaining_pawns)];
+            }
+
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        Some((stm, file, idx))
+    }
+
+    /// Index of the leading group when it is made of three pieces, the first of them in the
+    /// a1-d1-d4 triangle
+    fn encode_unique(&self, squares: &[usize; TB_PIECES]) -> u64 {
+        let encoding = Encoding::get();
+        let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+        let adjust1 = usize::from(s1 > s0);
+        let adjust2 = usize::from(s2 > s0) + usize::from(s2 > s1);
+
+        let idx = if off_diagonal(s0) != 0 {
+            (encoding.map_a1d1d4[s0] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+        } else if off_diagonal(s1) != 0 {
+            (6 * 63 + (s0 / 8) * 28 + encoding.map_b1h1h7[s1]) * 62 + s2 - adjust2
+        } else if off_diagonal(s2) != 0 {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + (s0 / 8) * 7 * 28
+                + (s1 / 8 - adjust1) * 28
+                + encoding.map_b1h1h7[s2]
+        } else {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + 4 * 7 * 28
+                + (s0 / 8) * 7 * 6
+                + (s1 / 8 - adjust1) * 6
+                + (s2 / 8 - adjust2)
+        };
+
+        idx as u64
+    }
+
+    /// Converts a stored DTZ value to plies
+    fn map_dtz(&self, file: usize, mut value: i32, wdl: Wdl) -> Option<i32> {
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+        let d = self.get(0, file);
+
+        if d.flags & FLAG_MAPPED != 0 {
+            let idx = d.map_idx[WDL_MAP[(wdl as i32 + 2) as usize]] + value as usize;
+            value = if d.flags & FLAG_WIDE != 0 {
+                read_u16(&self.data, self.map + 2 * idx)? as i32
+            } else {
+                *self.data.get(self.map + idx)? as i32
+            };
+        }
+
+        // Values are stored in moves unless they need the precision of plies
+        if (wdl == Wdl::Win && d.flags & FLAG_WIN_PLIES == 0)
+            || (wdl == Wdl::Loss && d.flags & FLAG_LOSS_PLIES == 0)
+            || wdl == Wdl::CursedWin
+            || wdl == Wdl::BlessedLoss
+        {
+            value *= 2;
+        }
+
+        Some(value + 1)
+    }
+
+    /// Reads the value at `idx`. The sparse index points close to the block holding it, from
+    /// which the Huffman codes are decoded until the symbol covering `idx` is found
+    fn decompress(&self, d: &PairsData, idx: u64) -> Option<u16> {
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(d.min_sym_len as u16);
+        }
+
+        let data = &self.data;
+        let entry = d.sparse_index + 6 * (idx / d.span) as usize;
+        let mut block = read_u32(data, entry)? as usize;
+        let mut offset = read_u16(data, entry + 4)? as i64;
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+        let block_length = |block: usize| read_u16(data, d.block_lengths + 2 * block);
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? as i64 + 1;
+        }
+        while offset > block_length(block)? as i64 {
+            offset -= block_length(block)? as i64 + 1;
+            block += 1;
+        }
+
+        let mut ptr = d.data + block * d.block_size;
+        let mut buf = read_u64_be(data, ptr)?;
+        let mut buf_size = 64;
+        ptr += 8;
+
+        let min_len = d.min_sym_len as usize;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf < *d.base64.get(len)? {
+                len += 1;
+            }
+
+            sym = ((buf - d.base64[len]) >> (64 - len - min_len)) as u16;
+            sym = sym.wrapping_add(read_u16(data, d.lowest_sym + 2 * len)?);
+
+            let values = *d.symlen.get(sym as usize)? as i64 + 1;
+            if offset < values {
+                break;
+            }
+
+            offset -= values;
+            len += min_len;
+            buf = buf.checked_shl(len as u32).unwrap_or(0);
+            buf_size -= len;
+
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= (read_u32_be(data, ptr).unwrap_or(0) as u64) << (64 - buf_size);
+                ptr += 4;
+            }
+        }
+
+        let mut sym = sym as usize;
+        while *d.symlen.get(sym)? != 0 {
+            let (left, right) = symbol_pair(data, d.btree, sym)?;
+            let values = *d.symlen.get(left)? as i64 + 1;
+
+            if offset < values {
+                sym = left;
+            } else {
+                offset -= values;
+                sym = right;
+            }
+        }
+
+        symbol_pair(data, d.btree, sym).map(|(value, _)| value as u16)
+    }
+}
+
+/// Reads the sizes and Huffman code of a subtable, returning the offset of the next one
+fn set_sizes(d: &mut PairsData, data: &[u8], mut offset: usize) -> Option<usize> {
+    d.flags = *data.get(offset)?;
+    offset += 1;
+
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        d.min_sym_len = *data.get(offset)?;
+        return Some(offset + 1);
+    }
+
+    let table_size = d.group_idx[d.group_len.iter().position(|&len| len == 0)?];
+    let header = data.get(offset..offset + 9)?;
+    d.block_size = 1usize.checked_shl(header[0] as u32)?;
+    d.span = 1u64.checked_shl(header[1] as u32)?;
+    d.sparse_index_size = table_size.div_ceil(d.span) as usize;
+    d.blocks = read_u32(data, offset + 3)? as usize;
+    d.block_lengths_size = d.blocks + header[2] as usize;
+    let max_sym_len = header[7] as usize;
+    d.min_sym_len = header[8];
+    offset += 9;
+
+    let min_sym_len = d.min_sym_len as usize;
+    let lengths = max_sym_len.checked_sub(min_sym_len)? + 1;
+    d.lowest_sym = offset;
+    d.base64 = vec![0; lengths];
+
+    // Longer codes have lower values, so the lowest code of each length is derived from that
+    // of the next longer one and then left aligned
+    for i in (0..lengths - 1).rev() {
+        let lowest = read_u16(data, offset + 2 * i)? as u64;
+        let next_lowest = read_u16(data, offset + 2 * (i + 1))? as u64;
+        d.base64[i] = d.base64[i + 1]
+            .wrapping_add(lowest)
+            .wrapping_sub(next_lowest)
+            / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        let shift = 64usize.checked_sub(i + min_sym_len)?;
+        *base = base.checked_shl(shift as u32).unwrap_or(0);
+    }
+    offset += 2 * lengths;
+
+    let symbols = read_u16(data, offset)? as usize;
+    offset += 2;
+    d.btree = offset;
+    d.symlen = vec![0; symbols];
+
+    let mut visited = vec![false; symbols];
+    for sym in 0..symbols {
+        if !visited[sym] {
+            d.symlen[sym] = set_symlen(d, data, sym, &mut visited)?;
+        }
+    }
+
+    Some(offset + 3 * symbols + (symbols & 1))
+}
+
+/// Number of values a symbol expands to, minus one
+fn set_symlen(d: &mut PairsData, data: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+    visited[sym] = true;
+
+    let (left, right) = symbol_pair(data, d.btree, sym)?;
+    if right == 0xFFF {
+        return Some(0);
+    }
+
+    for child in [left, right] {
+        if !*visited.get(child)? {
+            d.symlen[child] = set_symlen(d, data, child, visited)?;
+        }
+    }
+
+    Some(d.symlen[left].wrapping_add(d.symlen[right]).wrapping_add(1))
+}
+
+/// Tables available for a material configuration
+struct TableFiles {
+    material: Material,
+    tables: [Option<Table>; 2],
+}
+
+impl TableFiles {
+    fn table(&self, kind: TableKind) -> Option<&Table> {
+        self.tables[kind as usize].as_ref()
+    }
+}
+
+/// Syzygy endgame tablebases found under the directories of the `SyzygyPath` option.
+///
+/// WDL tables tell whether a position is won, drawn or lost and are probed during the search,
+/// DTZ tables give the distance to the next capture or pawn move and are used at the root to
+/// keep only the moves that make progress. Files are mapped and their headers checked when
+/// the tables are opened, so probing does no file I/O besides reading the mapped pages.
+#[derive(Default)]
+pub struct Tablebases {
+    tables: HashMap<u64, TableFiles>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    /// Looks for tables in a list of directories separated by `:` (`;` on Windows)
+    pub fn open(paths: &str) -> Self {
+        let mut tablebases = Self::default();
+
+        for dir in paths.split(PATH_SEPARATOR).filter(|dir| !dir.is_empty()) {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+
+            for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+                let kind = match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("rtbw") => TableKind::Wdl,
+                    Some("rtbz") => TableKind::Dtz,
+                    _ => continue,
+                };
+                let Some(material) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(Material::from_name)
+                else {
+                    continue;
+                };
+
+                let files = tablebases
+                    .tables
+                    .entry(material.key())
+                    .or_insert_with(|| TableFiles {
+                        material,
+                        tables: Default::default(),
+                    });
+                if files.tables[kind as usize].is_some() {
+                    continue;
+                }
+
+                // Files that cannot be mapped or hold a broken header are left out
+                let Some(table) = Table::open(&path, material, kind) else {
+                    continue;
+                };
+                files.tables[kind as usize] = Some(table);
+
+                if kind == TableKind::Wdl {
+                    tablebases.max_pieces = tablebases.max_pieces.max(material.piece_count());
+                }
+            }
+        }
+
+        tablebases
+    }
+
+    /// Number of WDL and DTZ files opened
+    pub fn count(&self) -> usize {
+        self.tables
+            .values()
+            .map(|files| files.tables.iter().flatten().count())
+            .sum()
+    }
+
+    /// Largest number of pieces covered by the WDL tables, 0 without tables
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Whether the position is covered by the tables, which assume no castling rights
+    pub fn can_probe(&self, board: &Board) -> bool {
+        board.castling_rights.0 == 0 && board.occupied() <= self.max_pieces
+    }
+
+    /// Result of the position with perfect play, ignoring the 50-move counter
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        self.search(board, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Distance in plies to the next capture or pawn move with perfect play, positive when
+    /// winning and negative when losing. Cursed wins and blessed losses are 100 plies further
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        let (wdl, state) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if state == ProbeState::ZeroingBestMove {
+            return Some(wdl.dtz_before_zeroing());
+        }
+
+        match self.probe_table(board, TableKind::Dtz, wdl)? {
+            TableValue::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+                Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum())
+            }
+            // The table stores the other side to move, the value comes from a 1-ply search
+            TableValue::ChangeStm => {
+                let mut min_dtz = i32::MAX;
+
+                for m in board.generate_legal_moves() {
+                    let zeroing = is_zeroing(board, m);
+                    let mut child = *board;
+                    child.make_move(m);
+
+                    let mut dtz = if zeroing {
+                        -self.search(&child, false)?.0.dtz_before_zeroing()
+                    } else {
+                        -self.probe_dtz(&child)?
+                    };
+
+                    if dtz == 1 && is_checkmate(&child) {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                        min_dtz = dtz;
+                    }
+                }
+
+                Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+            }
+        }
+    }
+
+    /// Keeps the root moves with the best result under the 50-move rule: every move winning
+    /// in time, or failing that the ones drawing or delaying the loss the most. Returns `None`
+    /// if the position or one of its successors is not covered by the tables
+    pub fn rank_root_moves(&self, board: &Board) -> Option<Vec<Move>> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        let halfmoves = board.halfmoves as i32;
+        let mut ranked = Vec::new();
+
+        for m in board.generate_legal_moves() {
+            let mut child = *board;
+            child.make_move(m);
+
+            let mut dtz = if child.halfmoves == 0 {
+                (-self.probe_wdl(&child)?).dtz_before_zeroing()
+            } else if child.halfmoves >= 100 {
+                0
+            } else {
+                let dtz = -self.probe_dtz(&child)?;
+                dtz + dtz.signum()
+            };
+
+            if dtz == 2 && is_checkmate(&child) {
+                dtz = 1;
+            }
+
+            let rank = match dtz.signum() {
+                1 if dtz + halfmoves <= 99 => MAX_DTZ,
+                1 => MAX_DTZ - (dtz + halfmoves),
+                -1 if -dtz * 2 + halfmoves < 100 => -MAX_DTZ,
+                -1 => -MAX_DTZ + (-dtz + halfmoves),
+                _ => 0,
+            };
+            ranked.push((rank, m));
+        }
+
+        let best = ranked.iter().map(|&(rank, _)| rank).max()?;
+        Some(
+            ranked
+                .into_iter()
+                .filter(|&(rank, _)| rank == best)
+                .map(|(_, m)| m)
+                .collect(),
+        )
+    }
+
+    /// Probes the position, first resolving captures (and pawn moves when `zeroing` is set)
+    /// because tables may store any value for positions where one of them is the best move
+    fn search(&self, board: &Board, zeroing: bool) -> Option<(Wdl, ProbeState)> {
+        let moves = board.generate_legal_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+
+        for &m in &moves {
+            let is_pawn = board
+                .piece_at(m.get_source())
+                .is_some_and(|piece| piece.is_pawn());
+            if !m.get_type().is_capture() && (!zeroing || !is_pawn) {
+                continue;
+            }
+
+            searched += 1;
+            let mut child = *board;
+            child.make_move(m);
+            let value = -self.search(&child, false)?.0;
+
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, ProbeState::ZeroingBestMove));
+                }
+            }
+        }
+
+        // With every move already searched the stored value does not matter
+        let all_searched = searched > 0 && searched == moves.len();
+        let value = if all_searched {
+            best
+        } else {
+            match self.probe_table(board, TableKind::Wdl, Wdl::Draw)? {
+                TableValue::Value(value) => Wdl::from_value(value)?,
+                TableValue::ChangeStm => return None,
+            }
+        };
+
+        if best >= value {
+            let state = if best > Wdl::Draw || all_searched {
+                ProbeState::ZeroingBestMove
+            } else {
+                ProbeState::Ok
+            };
+            return Some((best, state));
+        }
+
+        Some((value, ProbeState::Ok))
+    }
+
+    fn probe_table(&self, board: &Board, kind: TableKind, wdl: Wdl) -> Option<TableValue> {
+        if board.occupied() == 2 {
+            return Some(TableValue::Value(0)); // KvK
+        }
+
+        let material = Material::from_board(board);
+        let (files, flip) = match self.tables.get(&material.key()) {
+            Some(files) => (files, false),
+            None => (self.tables.get(&material.swapped().key())?, true),
+        };
+
+        // Symmetric tables only store White to move
+        let flip =
+            flip || (files.material == files.material.swapped() && board.side == Colour::Black);
+        files.table(kind)?.probe(board, flip, wdl)
+    }
+}
+
+/// Captures and pawn moves reset the 50-move counter
+fn is_zeroing(board: &Board, m: Move) -> bool {
+    m.get_type().is_capture()
+        || board
+            .piece_at(m.get_source())
+            .is_some_and(|piece| piece.is_pawn())
+}
+
+fn is_checkmate(board: &Board) -> bool {
+    board.is_attacked_by(board.king_square(board.side), !board.side)
+        && board.generate_legal_moves().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_tables() {
+        let encoding = Encoding::get();
+
+        let king_pairs = encoding.map_kk.iter().flatten().max().unwrap() + 1;
+        assert_eq!(king_pairs, 462);
+        assert_eq!(encoding.map_b1h1h7.iter().max(), Some(&27));
+        assert_eq!(encoding.map_a1d1d4.iter().max(), Some(&9));
+        assert_eq!(encoding.map_pawns[Square::from("a2").index()], 47);
+        assert_eq!(encoding.map_pawns[Square::from("e7").index()], 0);
+        assert_eq!(encoding.binomial[3][48], 17296);
+
+        let material = Material::from_name("KRPvKR").unwrap();
+        assert_eq!(material.piece_count(), 5);
+        assert_eq!(material.pawns(0), 1);
+        assert!(Material::from_name("KRvR").is_none());
+    }
+
+    /// Squares of the a1-d1-d4 triangle, every position has an equivalent one with the
+    /// leading piece inside it
+    const TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+
+    /// Values of a subtable compressed the way the tables are: pairs of frequent symbols are
+    /// merged into new symbols, and symbols are written as canonical Huffman codes
+    struct Compressed {
+        header: Vec<u8>,
+        sparse_index: Vec<u8>,
+        block_lengths: Vec<u8>,
+        blocks: Vec<u8>,
+    }
+
+    fn compress(values: &[u16], flags: u8) -> Compressed {
+        const BLOCK_BITS: usize = 256;
+        const SPAN: usize = 64;
+
+        let mut compressed = Compressed {
+            header: vec![flags],
+            sparse_index: Vec::new(),
+            block_lengths: Vec::new(),
+            blocks: Vec::new(),
+        };
+        if values.iter().all(|&value| value == values[0]) {
+            compressed.header[0] |= FLAG_SINGLE_VALUE;
+            compressed.header.push(values[0] as u8);
+            return compressed;
+        }
+
+        let mut symbols: Vec<(u16, u16)> = Vec::new();
+        let mut sequence: Vec<usize> = values
+            .iter()
+            .map(
+                |&value| match symbols.iter().position(|&(v, _)| v == value) {
+                    Some(sym) => sym,
+                    None => {
+                        symbols.push((value, 0xFFF));
+                        symbols.len() - 1
+                    }
+                },
+            )
+            .collect();
+
+        for _ in 0..6 {
+            let mut counts: HashMap<(usize, usize), usize> = HashMap::new();
+            for pair in sequence.windows(2) {
+                *counts.entry((pair[0], pair[1])).or_default() += 1;
+            }
+            let Some((&(left, right), &count)) = counts
+                .iter()
+                .max_by_key(|&(&pair, &count)| (count, std::cmp::Reverse(pair)))
+            else {
+                break;
+            };
+            if count < 8 {
+                break;
+            }
+
+            symbols.push((left as u16, right as u16));
+            let mut merged = Vec::with_capacity(sequence.len());
+            let mut i = 0;
+            while i < sequence.len() {
+                if i + 1 < sequence.len() && (sequence[i], sequence[i + 1]) == (left, right) {
+                    merged.push(symbols.len() - 1);
+                    i += 2;
+                } else {
+                    merged.push(sequence[i]);
+                    i += 1;
+                }
+            }
+            sequence = merged;
+        }
+
+        let expanded = |sym: usize| {
+            let mut stack = vec![sym];
+            let mut count = 0;
+            while let Some(sym) = stack.pop() {
+                match symbols[sym] {
+                    (_, 0xFFF) => count += 1,
+                    (left, right) => stack.extend([left as usize, right as usize]),
+                }
+            }
+            count
+        };
+
+        // Huffman code lengths, every symbol gets a code even if it only appears inside pairs
+        let mut frequencies = vec![1u64; symbols.len()];
+        sequence.iter().for_each(|&sym| frequencies[sym] += 1);
+        let mut parents = vec![usize::MAX; symbols.len()];
+        let mut heap: std::collections::BinaryHeap<_> = frequencies
+            .iter()
+            .enumerate()
+            .map(|(sym, &freq)| std::cmp::Reverse((freq, sym)))
+            .collect();
+        while heap.len() > 1 {
+            let std::cmp::Reverse((first, a)) = heap.pop().unwrap();
+            let std::cmp::Reverse((second, b)) = heap.pop().unwrap();
+            parents.push(usize::MAX);
+            parents[a] = parents.len() - 1;
+            parents[b] = parents.len() - 1;
+            heap.push(std::cmp::Reverse((first + second, parents.len() - 1)));
+        }
+        let lengths: Vec<usize> = (0..symbols.len())
+            .map(|mut node| {
+                let mut length = 0;
+                while parents[node] != usize::MAX {
+                    node = parents[node];
+                    length += 1;
+                }
+                length
+            })
+            .collect();
+        let (min_len, max_len) = (
+            *lengths.iter().min().unwrap(),
+            *lengths.iter().max().unwrap(),
+        );
+
+        // Canonical codes: longer codes get lower symbol numbers and lower values
+        let mut order: Vec<usize> = (0..symbols.len()).collect();
+        order.sort_by_key(|&sym| (std::cmp::Reverse(lengths[sym]), sym));
+        let mut number = vec![0; symbols.len()];
+        order
+            .iter()
+            .enumerate()
+            .for_each(|(n, &sym)| number[sym] = n);
+
+        let mut count = vec![0u64; max_len + 2];
+        lengths.iter().for_each(|&len| count[len] += 1);
+        let mut lowest = vec![0u64; max_len + 2];
+        let mut base = vec![0u64; max_len + 2];
+        for len in (min_len..max_len).rev() {
+            lowest[len] = lowest[len + 1] + count[len + 1];
+            base[len] = (base[len + 1] + count[len + 1]) / 2;
+        }
+        let code = |sym: usize| base[lengths[sym]] + number[sym] as u64 - lowest[lengths[sym]];
+
+        let header = &mut compressed.header;
+        header.extend([5, SPAN.trailing_zeros() as u8, 0]);
+        let blocks_at = header.len();
+        header.extend([0, 0, 0, 0, max_len as u8, min_len as u8]);
+        for lowest in &lowest[min_len..=max_len] {
+            header.extend((*lowest as u16).to_le_bytes());
+        }
+        header.extend((symbols.len() as u16).to_le_bytes());
+        for &sym in &order {
+            let (left, right) = match symbols[sym] {
+                (value, 0xFFF) => (value as usize, 0xFFF),
+                (left, right) => (number[left as usize], number[right as usize]),
+            };
+            header.extend([
+                left as u8,
+                ((left >> 8) | (right & 0xF) << 4) as u8,
+                (right >> 4) as u8,
+            ]);
+        }
+        if symbols.len() % 2 == 1 {
+            header.push(0);
+        }
+
+        // Codes are packed in blocks starting with their most significant bit
+        let mut starts = Vec::new();
+        let (mut bits, mut block_values) = (BLOCK_BITS, 0);
+        for &sym in &sequence {
+            let len = lengths[sym];
+            if bits + len > BLOCK_BITS {
+                if !starts.is_empty() {
+                    compressed
+                        .block_lengths
+                        .extend((block_values as u16 - 1).to_le_bytes());
+                }
+                starts.push(starts.last().map_or(0, |&start| start + block_values));
+                compressed.blocks.extend([0; BLOCK_BITS / 8]);
+                (bits, block_values) = (0, 0);
+            }
+
+            let block = compressed.blocks.len() - BLOCK_BITS / 8;
+            for bit in 0..len {
+                if code(sym) >> (len - 1 - bit) & 1 != 0 {
+                    compressed.blocks[block + (bits + bit) / 8] |= 0x80 >> ((bits + bit) % 8);
+                }
+            }
+            bits += len;
+            block_values += expanded(sym);
+        }
+        compressed
+            .block_lengths
+            .extend((block_values as u16 - 1).to_le_bytes());
+        compressed.header[blocks_at..blocks_at + 4]
+            .copy_from_slice(&(starts.len() as u32).to_le_bytes());
+
+        // Each entry locates the value in the middle of its span
+        for first in (0..values.len()).step_by(SPAN) {
+            let target = first + SPAN / 2;
+            let block = starts.iter().rposition(|&start| start <= target).unwrap();
+            compressed.sparse_index.extend((block as u32).to_le_bytes());
+            compressed
+                .sparse_index
+                .extend(((target - starts[block]) as u16).to_le_bytes());
+        }
+
+        compressed
+    }
+
+    /// Writes a pawnless table whose subtables hold `values`, indexed by side to move
+    fn write_table(path: &Path, kind: TableKind, pieces: &[Piece], values: &[Vec<u16>]) {
+        let (magic, flags) = match kind {
+            TableKind::Wdl => (WDL_MAGIC, 0),
+            TableKind::Dtz => (DTZ_MAGIC, FLAG_WIN_PLIES | FLAG_LOSS_PLIES),
+        };
+        let mut data = magic.to_vec();
+        data.extend([1, 0]);
+        data.extend(pieces.iter().map(|&piece| piece_code(piece) * 0x11));
+        data.resize(data.len() + data.len() % 2, 0);
+
+        let subtables: Vec<Compressed> = values
+            .iter()
+            .map(|values| compress(values, flags))
+            .collect();
+        subtables.iter().for_each(|d| data.extend(&d.header));
+        if kind == TableKind::Dtz {
+            data.resize(data.len() + data.len() % 2, 0);
+        }
+        subtables.iter().for_each(|d| data.extend(&d.sparse_index));
+        subtables.iter().for_each(|d| data.extend(&d.block_lengths));
+        for d in &subtables {
+            data.resize((data.len() + 0x3F) & !0x3F, 0);
+            data.extend(&d.blocks);
+        }
+
+        fs::write(path, data).unwrap();
+    }
+
+    /// Builds a position from its pieces, `None` if the side not to move is in check
+    fn board_from_squares(placement: &[(Piece, usize)], side: Colour) -> Option<Board> {
+        let mut squares = [None; 64];
+        placement
+            .iter()
+            .for_each(|&(piece, sq)| squares[sq] = Some(piece));
+
+        let ranks: Vec<String> = (0..8)
+            .rev()
+            .map(|rank| {
+                let mut text = String::new();
+                let mut empty = 0;
+                for piece in &squares[rank * 8..rank * 8 + 8] {
+                    match piece {
+                        Some(piece) => {
+                            if empty > 0 {
+                                text += &empty.to_string();
+                            }
+                            text.push(piece.to_char());
+                            empty = 0;
+                        }
+                        None => empty += 1,
+                    }
+                }
+                if empty > 0 {
+                    text += &empty.to_string();
+                }
+                text
+            })
+            .collect();
+        let side = if side == Colour::White { 'w' } else { 'b' };
+
+        Board::from_fen(&format!("{} {} - - 0 1", ranks.join("/"), side)).ok()
+    }
+
+    /// Result of a position with perfect play, with the number of plies to mate
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    enum Solved {
+        Win(i32),
+        Loss(i32),
+        Draw,
+    }
+
+    impl Solved {
+        fn wdl(self) -> Wdl {
+            match self {
+                Self::Win(_) => Wdl::Win,
+                Self::Loss(_) => Wdl::Loss,
+                Self::Draw => Wdl::Draw,
+            }
+        }
+
+        /// The only captures lose the win, so the distance to zeroing is the distance to mate
+        fn dtz(self) -> i32 {
+            match self {
+                Self::Win(plies) => plies,
+                Self::Loss(0) => -1,
+                Self::Loss(plies) => -plies,
+                Self::Draw => 0,
+            }
+        }
+    }
+
+    /// A position solved by the generator, along with its legal moves
+    struct SolvedPosition {
+        board: Board,
+        solved: Solved,
+        moves: Vec<(Move, Solved)>,
+    }
+
+    /// Solves the ending of a king and `piece` against a lone king by retrograde analysis, and
+    /// writes its WDL and DTZ tables to `dir`
+    fn generate(dir: &Path, piece: Piece) -> Vec<SolvedPosition> {
+        let name = format!("K{}vK", piece.to_char());
+        let material = Material::from_name(&name).unwrap();
+        let pieces = [Piece::WK, piece, Piece::BK];
+
+        // A table holding a single value is enough to index the positions
+        let path = dir.join(format!("{}.index", name));
+        write_table(&path, TableKind::Wdl, &pieces, &[vec![0], vec![0]]);
+        let index = Table::open(&path, material, TableKind::Wdl).unwrap();
+        let size = |stm: usize| {
+            let d = index.get(stm, 0);
+            d.group_idx[d.group_len.iter().position(|&len| len == 0).unwrap()] as usize
+        };
+
+        let mut states: HashMap<(usize, u64), usize> = HashMap::new();
+        let mut boards = Vec::new();
+        for side in [Colour::White, Colour::Black] {
+            for king in TRIANGLE {
+                for (sq, other) in (0..64).flat_map(|sq| (0..64).map(move |other| (sq, other))) {
+                    if sq == king || other == king || other == sq {
+                        continue;
+                    }
+                    let placement = [(Piece::WK, king), (piece, sq), (Piece::BK, other)];
+                    let Some(board) = board_from_squares(&placement, side) else {
+                        continue;
+                    };
+
+                    let (stm, _, idx) = index.index(&board, false).unwrap();
+                    states.entry((stm, idx)).or_insert_with(|| {
+                        boards.push(board);
+                        boards.len() - 1
+                    });
+                }
+            }
+        }
+
+        // Captures leave two kings, every other move leads to another position of the table
+        let children: Vec<Vec<(Move, Option<usize>)>> = boards
+            .iter()
+            .map(|board| {
+                let moves = board.generate_legal_moves();
+                moves
+                    .iter()
+                    .map(|&m| {
+                        let mut child = *board;
+                        child.make_move(m);
+                        let (stm, _, idx) = index.index(&child, false).unwrap();
+                        let state = (!m.get_type().is_capture()).then(|| states[&(stm, idx)]);
+                        (m, state)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut solved: Vec<Option<Solved>> = boards
+            .iter()
+            .zip(&children)
+            .map(|(board, moves)| {
+                let in_check = board.is_attacked_by(board.king_square(board.side), !board.side);
+                match (moves.is_empty(), in_check) {
+                    (true, true) => Some(Solved::Loss(0)),
+                    (true, false) => Some(Solved::Draw),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        for ply in 1.. {
+            let previous = solved.clone();
+            let value = |state: Option<usize>| state.map_or(Some(Solved::Draw), |i| previous[i]);
+
+            for (i, moves) in children.iter().enumerate() {
+                if previous[i].is_some() {
+                    continue;
+                }
+
+                if moves
+                    .iter()
+                    .any(|&(_, state)| value(state) == Some(Solved::Loss(ply - 1)))
+                {
+                    solved[i] = Some(Solved::Win(ply));
+                } else if moves
+                    .iter()
+                    .all(|&(_, state)| matches!(value(state), Some(Solved::Win(_))))
+                {
+                    solved[i] = Some(Solved::Loss(ply));
+                }
+            }
+
+            if solved == previous {
+                break;
+            }
+        }
+        let solved: Vec<Solved> = solved
+            .into_iter()
+            .map(|solved| solved.unwrap_or(Solved::Draw))
+            .collect();
+
+        let mut wdl: Vec<Vec<u16>> = (0..2).map(|stm| vec![2; size(stm)]).collect();
+        let mut dtz = vec![0; size(0)];
+        for (&(stm, idx), &i) in &states {
+            wdl[stm][idx as usize] = (solved[i].wdl() as i32 + 2) as u16;
+            if let (0, Solved::Win(plies)) = (stm, solved[i]) {
+                dtz[idx as usize] = plies as u16 - 1;
+            }
+        }
+        drop(index);
+        fs::remove_file(&path).unwrap();
+
+        write_table(
+            &dir.join(format!("{}.rtbw", name)),
+            TableKind::Wdl,
+            &pieces,
+            &wdl,
+        );
+        write_table(
+            &dir.join(format!("{}.rtbz", name)),
+            TableKind::Dtz,
+            &pieces,
+            &[dtz],
+        );
+
+        boards
+            .into_iter()
+            .zip(children)
+            .enumerate()
+            .map(|(i, (board, moves))| SolvedPosition {
+                board,
+                solved: solved[i],
+                moves: moves
+                    .into_iter()
+                    .map(|(m, state)| (m, state.map_or(Solved::Draw, |state| solved[state])))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Swaps the colours of the pieces and mirrors the board vertically
+    fn mirrored(board: &Board) -> Board {
+        let placement: Vec<(Piece, usize)> = (0..64)
+            .filter_map(|sq| {
+                let piece = board.piece_at(Square::new(sq))?;
+                Some((Piece::ALL[piece as usize ^ 1], sq ^ 56))
+            })
+            .collect();
+        board_from_squares(&placement, !board.side).unwrap()
+    }
+
+    /// Runs against tables written for KQvK and KRvK by a small generator
+    #[test]
+    fn test_probe_generated_tables() {
+        let dir = std::env::temp_dir().join(format!("oxide-syzygy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let queen = generate(&dir, Piece::WQ);
+        let rook = generate(&dir, Piece::WR);
+        let tablebases = Tablebases::open(dir.to_str().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tablebases.count(), 4);
+        assert_eq!(tablebases.max_pieces(), 3);
+
+        // The longest mates take 10 and 16 moves
+        let longest = |positions: &[SolvedPosition]| {
+            positions
+                .iter()
+                .filter_map(|position| match position.solved {
+                    Solved::Win(plies) => Some(plies),
+                    _ => None,
+                })
+                .max()
+        };
+        assert_eq!(longest(&queen), Some(19));
+        assert_eq!(longest(&rook), Some(31));
+
+        for position in queen.iter().chain(&rook).step_by(5) {
+            let (board, solved) = (&position.board, position.solved);
+            let fen = board.to_fen();
+            assert_eq!(tablebases.probe_wdl(board), Some(solved.wdl()), "{}", fen);
+            assert_eq!(tablebases.probe_dtz(board), Some(solved.dtz()), "{}", fen);
+
+            // Tables only store White as the stronger side
+            let board = mirrored(board);
+            assert_eq!(tablebases.probe_wdl(&board), Some(solved.wdl()), "{}", fen);
+        }
+
+        // Every winning move is kept while the 50-move rule is far, only the fastest ones when
+        // it is close
+        let position = rook
+            .iter()
+            .find(|position| position.solved == Solved::Win(21))
+            .unwrap();
+        let ranked = |halfmoves: u8, expected: &dyn Fn(Solved) -> bool| {
+            let mut board = position.board;
+            board.halfmoves = halfmoves;
+            let mut moves = tablebases.rank_root_moves(&board).unwrap();
+            let mut winning: Vec<Move> = position
+                .moves
+                .iter()
+                .filter(|&&(_, solved)| expected(solved))
+                .map(|&(m, _)| m)
+                .collect();
+            moves.sort_by_key(|m| m.to_string());
+            winning.sort_by_key(|m| m.to_string());
+            assert_eq!(moves, winning);
+        };
+        ranked(0, &|solved| matches!(solved, Solved::Loss(_)));
+        ranked(99 - 21, &|solved| solved == Solved::Loss(20));
+    }
+
+    /// Runs against the 3 and 4 piece tables found in `SYZYGY_PATH`, or `resources/syzygy`
+    /// otherwise. The tables are not part of the repository, run with `cargo test -- --ignored`
+    /// once they are in place
+    #[test]
+    #[ignore = "needs the 3-4 piece Syzygy tables"]
+    fn test_probe_syzygy_tables() {
+        let path = std::env::var("SYZYGY_PATH")
+            .unwrap_or_else(|_| concat!(env!("CARGO_MANIFEST_DIR"), "/resources/syzygy").into());
+        let tablebases = Tablebases::open(&path);
+        assert!(
+            tablebases.max_pieces() >= 4,
+            "No 3-4 piece tables in {}",
+            path
+        );
+
+        for (fen, wdl) in [
+            ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", Wdl::Win),
+            ("4k3/8/8/8/8/8/8/3QK3 b - - 0 1", Wdl::Loss),
+            ("8/8/4k3/8/8/8/8/1r2K3 w - - 0 1", Wdl::Loss),
+            ("8/8/4k3/8/8/8/8/1n2K3 w - - 0 1", Wdl::Draw),
+            ("8/8/8/4k3/8/8/8/KBN5 w - - 0 1", Wdl::Win),
+            ("7k/8/8/8/8/8/7P/7K w - - 0 1", Wdl::Draw),
+            ("8/4P3/8/8/8/k7/8/4K3 w - - 0 1", Wdl::Win),
+            ("r7/8/3k4/8/8/3K4/8/7R w - - 0 1", Wdl::Draw),
+            ("k6Q/8/1K6/8/8/8/8/8 b - - 0 1", Wdl::Loss),
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(tablebases.probe_wdl(&board), Some(wdl), "{}", fen);
+        }
+
+        // Mate in one, then draws
+        let board = Board::from_fen("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_dtz(&board), Some(1));
+
+        for fen in [
+            "8/8/4k3/8/8/8/8/1n2K3 w - - 0 1",
+            "7k/8/8/8/8/8/7P/7K w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(tablebases.probe_dtz(&board), Some(0), "{}", fen);
+        }
+
+        // Without captures or pawn moves on the board, the DTZ of a position is one more than
+        // the best one among its successors, which come from the table of the other side.
+        // Tables storing moves instead of plies may be off by one
+        for fen in [
+            "8/8/8/8/3k4/8/8/KR6 w - - 0 1",
+            "8/8/8/8/3k4/8/8/KR6 b - - 0 1",
+            "8/8/8/4k3/8/8/8/KQ6 w - - 0 1",
+            "k7/8/1K6/8/8/8/8/6Q1 b - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let wdl = tablebases.probe_wdl(&board).unwrap();
+            let dtz = tablebases.probe_dtz(&board).unwrap();
+            assert_eq!(dtz.signum(), wdl.signum(), "{}", fen);
+
+            let children = board.generate_legal_moves().into_iter().map(|m| {
+                let mut child = board;
+                child.make_move(m);
+                -tablebases.probe_dtz(&child).unwrap()
+            });
+            let best = if dtz > 0 {
+                children.filter(|&dtz| dtz > 0).min()
+            } else {
+                children.min()
+            };
+            let expected = best.unwrap() + dtz.signum();
+            assert!((expected - dtz).abs() <= 1, "{}: {} {}", fen, dtz, expected);
+        }
+
+        // A winning promotion
+        let board = Board::from_fen("8/4P3/8/8/8/k7/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_dtz(&board), Some(1));
+
+        let moves: Vec<String> = tablebases
+            .rank_root_moves(&board)
+            .unwrap()
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        assert!(moves.contains(&"e7e8q".to_string()));
+        assert!(!moves.contains(&"e7e8n".to_string()));
+    }
+}
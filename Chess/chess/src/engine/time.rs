@@ -0,0 +1,149 @@
+use crate::game::piece::Colour;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MOVES_TO_GO: u64 = 25;
+
+/// Limits received from the UCI `go` command, times are given in milliseconds
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,
+    pub time: [Option<u64>; 2],
+    pub inc: [u64; 2],
+    pub movestogo: Option<u64>,
+    pub infinite: bool,
+    pub ponder: bool,
+}
+
+/// Keeps track of the time budget of a search.
+///
+/// The soft limit is checked between iterations, when exceeded no new iteration is started.
+/// The hard limit is checked inside the search, which is aborted as soon as it is exceeded.
+/// Neither is checked while pondering.
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+}
+
+impl TimeManager {
+    pub fn new(limits: &SearchLimits, side: Colour, move_overhead: u64) -> Self {
+        let (soft, hard) = if limits.infinite {
+            (None, None)
+        } else if let Some(movetime) = limits.movetime {
+            let budget = movetime.saturating_sub(move_overhead).max(1);
+            (Some(budget), Some(budget))
+        } else if let Some(time) = limits.time[side as usize] {
+            let inc = limits.inc[side as usize];
+            let moves_to_go = limits.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+            let available = time.saturating_sub(move_overhead).max(1);
+            let max_budget = (available * 4 / 5).max(1);
+
+            let soft = (available / moves_to_go + inc * 3 / 4).min(max_budget);
+            let hard = (soft * 3).min(max_budget);
+            (Some(soft), Some(hard))
+        } else {
+            (None, None)
+        };
+
+        Self {
+            start: Instant::now(),
+            soft_limit: soft.map(Duration::from_millis),
+            hard_limit: hard.map(Duration::from_millis),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Time charged to the move: pondering is free, so after a `ponderhit` the limits count
+    /// from it instead of from the start of the search
+    fn used(&self, ponderhit: Option<Instant>) -> Duration {
+        ponderhit.unwrap_or(self.start).elapsed()
+    }
+
+    pub fn soft_expired(&self, ponderhit: Option<Instant>) -> bool {
+        self.soft_limit
+            .is_some_and(|limit| self.used(ponderhit) >= limit)
+    }
+
+    pub fn hard_expired(&self, ponderhit: Option<Instant>) -> bool {
+        self.hard_limit
+            .is_some_and(|limit| self.used(ponderhit) >= limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits_ms(limits: &SearchLimits, side: Colour) -> (Option<u64>, Option<u64>) {
+        let tm = TimeManager::new(limits, side, 10);
+        let ms = |limit: Option<Duration>| limit.map(|d| d.as_millis() as u64);
+        (ms(tm.soft_limit), ms(tm.hard_limit))
+    }
+
+    #[test]
+    fn test_time_limits() {
+        let clock = SearchLimits {
+            time: [Some(60_000), Some(1_000)],
+            inc: [1_000, 1_000],
+            ..Default::default()
+        };
+        assert_eq!(limits_ms(&clock, Colour::White), (Some(3149), Some(9447)));
+
+        // Little time left: both limits are capped to 80% of the clock
+        assert_eq!(limits_ms(&clock, Colour::Black), (Some(789), Some(792)));
+
+        let movestogo = SearchLimits {
+            movestogo: Some(5),
+            ..clock
+        };
+        assert_eq!(
+            limits_ms(&movestogo, Colour::White),
+            (Some(12_748), Some(38_244))
+        );
+
+        let movetime = SearchLimits {
+            movetime: Some(500),
+            ..Default::default()
+        };
+        assert_eq!(limits_ms(&movetime, Colour::White), (Some(490), Some(490)));
+
+        let infinite = SearchLimits {
+            infinite: true,
+            ..clock
+        };
+        assert_eq!(limits_ms(&infinite, Colour::White), (None, None));
+        assert_eq!(
+            limits_ms(&SearchLimits::default(), Colour::White),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_ponderhit_time() {
+        let limits = SearchLimits {
+            movetime: Some(1_000),
+            ponder: true,
+            ..Default::default()
+        };
+        let mut tm = TimeManager::new(&limits, Colour::White, 0);
+
+        // Pretend the engine has been pondering for five seconds
+        tm.start -= Duration::from_secs(5);
+        assert!(tm.soft_expired(None));
+        assert!(tm.hard_expired(None));
+
+        // The budget only starts counting once the move is played
+        let ponderhit = Instant::now();
+        assert!(!tm.soft_expired(Some(ponderhit)));
+        assert!(!tm.hard_expired(Some(ponderhit)));
+
+        let ponderhit = Instant::now() - Duration::from_millis(1_500);
+        assert!(tm.soft_expired(Some(ponderhit)));
+        assert!(tm.hard_expired(Some(ponderhit)));
+    }
+}
@@ -1,5 +1,6 @@
 use super::{
-    constants::{bishop_attacks, rook_attacks, KING_ATTACKS, KNIGHT_ATTACKS},
+    constants::{KING_ATTACKS, KNIGHT_ATTACKS},
+    magic::{bishop_attacks, rook_attacks},
     moves::MoveKind,
     square::Square,
     zobrist::ZHash,
@@ -11,6 +12,73 @@ use crate::game::{
     piece::{Colour, Piece},
 };
 
+/// Largest halfmove clock accepted in a FEN, the 75-move rule ends any game by then. Keeps the
+/// clock from overflowing as moves are made
+const MAX_HALFMOVES: u8 = 150;
+
+/// Pieces removed from and added to the board by the last move, used to update the NNUE
+/// accumulators incrementally instead of recomputing them from scratch
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MoveDelta {
+    pub removed: [Option<(Piece, Square)>; 2],
+    pub added: [Option<(Piece, Square)>; 2],
+}
+
+/// State a move destroys, kept by the caller of [`Board::make_move`] to take the move back
+#[derive(Copy, Clone, Debug)]
+pub struct Undo {
+    captured: Option<Piece>,
+    castling_rights: CastlingRights,
+    en_passant: Option<Square>,
+    halfmoves: u8,
+    hash: ZHash,
+    delta: MoveDelta,
+}
+
+/// Reasons a FEN string is rejected by [`Board::from_fen`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum FenError {
+    FieldCount(usize),
+    RankCount(usize),
+    /// A rank, numbered from 1, that does not add up to eight squares
+    RankLength(usize),
+    PieceChar(char),
+    KingCount(Colour, usize),
+    PawnOnBackRank,
+    Side(String),
+    Castling(String),
+    /// A castling right whose king or rook has left its starting square
+    CastlingRight(char),
+    EnPassant(String),
+    Counter(String),
+    OpponentInCheck,
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::FieldCount(count) => write!(f, "expected 4 to 6 fields, found {}", count),
+            FenError::RankCount(count) => write!(f, "expected 8 ranks, found {}", count),
+            FenError::RankLength(rank) => write!(f, "rank {} does not have 8 squares", rank),
+            FenError::PieceChar(token) => write!(f, "invalid piece character '{}'", token),
+            FenError::KingCount(colour, count) => {
+                write!(f, "{:?} has {} kings instead of one", colour, count)
+            }
+            FenError::PawnOnBackRank => write!(f, "pawns can not stand on the first or last rank"),
+            FenError::Side(side) => write!(f, "invalid side to move '{}'", side),
+            FenError::Castling(rights) => write!(f, "invalid castling rights '{}'", rights),
+            FenError::CastlingRight(token) => write!(
+                f,
+                "castling right '{}' without its king and rook on their starting squares",
+                token
+            ),
+            FenError::EnPassant(square) => write!(f, "impossible en passant square '{}'", square),
+            FenError::Counter(counter) => write!(f, "invalid move counter '{}'", counter),
+            FenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Board {
     pub pieces: [BitBoard; 6],
@@ -20,9 +88,14 @@ pub struct Board {
 
     pub side: Colour,
     pub castling_rights: CastlingRights,
+    /// Starting squares of the castling rooks by colour, the king side one first. They are only
+    /// meaningful while the matching castling right is held
+    pub castling_rooks: [[Square; 2]; 2],
     pub en_passant: Option<Square>,
     pub halfmoves: u8,
+    pub fullmoves: u16,
     pub hash: ZHash,
+    pub delta: MoveDelta,
 }
 
 impl Board {
@@ -33,14 +106,20 @@ impl Board {
             piece_map: [None; Square::COUNT],
             en_passant: None,
             castling_rights: CastlingRights::NONE,
+            castling_rooks: [
+                [Square::new(7), Square::new(0)],
+                [Square::new(63), Square::new(56)],
+            ],
             halfmoves: 0,
+            fullmoves: 1,
             side: Colour::White,
             hash: ZHash::NULL,
+            delta: MoveDelta::default(),
         }
     }
 
     pub fn default() -> Self {
-        Self::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+        Self::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap()
     }
 
     pub fn piece_at(&self, square: Square) -> Option<Piece> {
@@ -70,11 +149,63 @@ impl Board {
             as usize
     }
 
-    pub fn make_move(&mut self, m: Move) {
+    fn undo(&self, captured: Option<Piece>) -> Undo {
+        Undo {
+            captured,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmoves: self.halfmoves,
+            hash: self.hash,
+            delta: self.delta,
+        }
+    }
+
+    /// Passes the turn to the opponent, used by null move pruning
+    pub fn make_null_move(&mut self) -> Undo {
+        let undo = self.undo(None);
+        if let Some(square) = self.en_passant {
+            self.en_passant = None;
+            self.hash.hash_enpassant(square);
+        }
+
+        self.halfmoves += 1;
+        self.delta = MoveDelta::default();
+        self.side = !self.side;
+        self.hash.hash_side();
+        #[cfg(debug_assertions)]
+        assert_eq!(self.hash, ZHash::new(self), "Hash mismatch after null move");
+        undo
+    }
+
+    pub fn unmake_null_move(&mut self, undo: Undo) {
+        self.side = !self.side;
+        self.restore(undo);
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            self.hash,
+            ZHash::new(self),
+            "Hash mismatch after unmaking null move"
+        );
+    }
+
+    /// Plays a move, returning what is needed to take it back with [`Board::unmake_move`]
+    pub fn make_move(&mut self, m: Move) -> Undo {
         let (src, dest) = (m.get_source(), m.get_dest());
         let src_piece = self.piece_at(src).expect("Invalid source piece");
         let move_type = m.get_type();
+        let captured = match move_type {
+            MoveKind::EnPassant => dest
+                .jump(0, -src_piece.colour().forward())
+                .and_then(|square| self.piece_at(square)),
+            _ if move_type.is_capture() => self.piece_at(dest),
+            _ => None,
+        };
+        let undo = self.undo(captured);
         let old_rights = self.castling_rights;
+        let mut delta = MoveDelta {
+            removed: [Some((src_piece, src)), None],
+            added: [Some((src_piece, dest)), None],
+        };
 
         if let Some(square) = self.en_passant {
             self.en_passant = None;
@@ -87,30 +218,16 @@ impl Board {
             self.halfmoves += 1;
         }
 
+        // Rights are lost when the king moves and when a castling rook moves or is captured
+        let mut lost = self.rights_lost(src) | self.rights_lost(dest);
         if src_piece.is_king() {
-            if src_piece.colour() == Colour::White {
-                let new_rights =
-                    CastlingRights(old_rights.0 & !(CastlingRights::WK | CastlingRights::WQ));
-                self.castling_rights = new_rights;
-                self.hash.swap_castle(old_rights, new_rights);
-            } else {
-                let new_rights =
-                    CastlingRights(old_rights.0 & !(CastlingRights::BK | CastlingRights::BQ));
-                self.castling_rights = new_rights;
-                self.hash.swap_castle(old_rights, new_rights);
-            }
-        } else if src_piece.is_rook() {
-            let new_rights = match (src_piece.colour(), src.index()) {
-                (Colour::White, 0) => CastlingRights(old_rights.0 & !CastlingRights::WQ), // a1
-                (Colour::White, 7) => CastlingRights(old_rights.0 & !CastlingRights::WK), // h1
-                (Colour::Black, 56) => CastlingRights(old_rights.0 & !CastlingRights::BQ), // a8
-                (Colour::Black, 63) => CastlingRights(old_rights.0 & !CastlingRights::BK), // h8
-                _ => old_rights,
-            };
-            if new_rights != old_rights {
-                self.castling_rights = new_rights;
-                self.hash.swap_castle(old_rights, new_rights);
-            }
+            let colour = src_piece.colour();
+            lost |= CastlingRights::flag(colour, true) | CastlingRights::flag(colour, false);
+        }
+        let new_rights = CastlingRights(old_rights.0 & !lost);
+        if new_rights != old_rights {
+            self.castling_rights = new_rights;
+            self.hash.swap_castle(old_rights, new_rights);
         }
 
         match move_type {
@@ -125,6 +242,7 @@ impl Board {
                 }
             }
             MoveKind::Capture => {
+                delta.removed[1] = self.piece_at(dest).map(|piece| (piece, dest));
                 self.remove_piece(dest);
                 self.remove_piece(src);
                 self.set_piece(src_piece, dest);
@@ -133,210 +251,141 @@ impl Board {
                 let captured_pawn_square = dest
                     .jump(0, -src_piece.colour().forward())
                     .expect("Off the board en_passant");
+                delta.removed[1] = self
+                    .piece_at(captured_pawn_square)
+                    .map(|piece| (piece, captured_pawn_square));
                 self.remove_piece(captured_pawn_square);
                 self.remove_piece(src);
                 self.set_piece(src_piece, dest);
             }
             MoveKind::Castle => {
-                let is_kingside = dest.col() > src.col();
-                let (rook_src_col, rook_dest_col) = if is_kingside { (7, 5) } else { (0, 3) };
-                let row = src.row();
-                let rook_src = Square::from_row_col(row, rook_src_col);
-                let rook_dest = Square::from_row_col(row, rook_dest_col);
-                let rook_piece = self.piece_at(rook_src).expect("Expected rook");
+                // The king takes its own rook, both end on the same files as in standard chess
+                let (king_dest, rook_dest) = castling_squares(src, dest);
+                let rook_piece = self.piece_at(dest).expect("Expected rook");
+                delta.removed[1] = Some((rook_piece, dest));
+                delta.added = [Some((src_piece, king_dest)), Some((rook_piece, rook_dest))];
 
                 self.remove_piece(src);
-                self.remove_piece(rook_src);
-                self.set_piece(src_piece, dest);
+                self.remove_piece(dest);
+                self.set_piece(src_piece, king_dest);
                 self.set_piece(rook_piece, rook_dest);
             }
             _ => {
                 #[cfg(debug_assertions)]
                 assert!(move_type.is_promotion(), "Expected a promotion move");
                 let promo_piece = move_type.get_promotion(src_piece.colour());
+                delta.added[0] = Some((promo_piece, dest));
                 self.remove_piece(src);
                 if move_type.is_capture() {
+                    delta.removed[1] = self.piece_at(dest).map(|piece| (piece, dest));
                     self.remove_piece(dest);
                 }
                 self.set_piece(promo_piece, dest);
             }
         }
 
+        self.delta = delta;
+        if self.side == Colour::Black {
+            self.fullmoves += 1;
+        }
         self.side = !self.side;
         self.hash.hash_side();
         #[cfg(debug_assertions)]
         assert_eq!(self.hash, ZHash::new(self), "Hash mismatch after move");
+        undo
     }
 
-    fn generate_pseudo_moves(&self, side: Colour) -> Vec<Move> {
-        let mut moves = Vec::with_capacity(64);
-        let side_idx = side as usize;
-
-        // Pawn moves
-        let mut pawn_bb = self.pieces[Piece::WP.index()] & self.sides[side_idx];
-        while pawn_bb != BitBoard::EMPTY {
-            let src = pawn_bb.lsb();
-            moves.extend(all_pawn_moves(
-                src,
-                if side == Colour::White {
-                    Piece::WP
-                } else {
-                    Piece::BP
-                },
-                self,
-            ));
-            pawn_bb = pawn_bb.pop_bit(src);
-        }
-
-        // Knight moves
-        let mut knight_bb = self.pieces[Piece::WN.index()] & self.sides[side_idx];
-        while knight_bb != BitBoard::EMPTY {
-            let src = knight_bb.lsb();
-            moves.extend(all_knight_moves(src));
-            knight_bb = knight_bb.pop_bit(src);
-        }
+    /// Takes back the last move played, which has to be `m`
+    pub fn unmake_move(&mut self, m: Move, undo: Undo) {
+        let (src, dest) = (m.get_source(), m.get_dest());
+        let move_type = m.get_type();
 
-        // Bishop moves
-        let mut bishop_bb = self.pieces[Piece::WB.index()] & self.sides[side_idx];
-        while bishop_bb != BitBoard::EMPTY {
-            let src = bishop_bb.lsb();
-            moves.extend(all_bishop_moves(src, self));
-            bishop_bb = bishop_bb.pop_bit(src);
+        self.side = !self.side;
+        if self.side == Colour::Black {
+            self.fullmoves -= 1;
         }
 
-        // Rook moves
-        let mut rook_bb = self.pieces[Piece::WR.index()] & self.sides[side_idx];
-        while rook_bb != BitBoard::EMPTY {
-            let src = rook_bb.lsb();
-            moves.extend(all_rook_moves(src, self));
-            rook_bb = rook_bb.pop_bit(src);
+        if move_type == MoveKind::Castle {
+            let (king_dest, rook_dest) = castling_squares(src, dest);
+            let king = self.piece_at(king_dest).expect("Expected king");
+            let rook = self.piece_at(rook_dest).expect("Expected rook");
+            self.remove_piece(king_dest);
+            self.remove_piece(rook_dest);
+            self.set_piece(king, src);
+            self.set_piece(rook, dest);
+            self.restore(undo);
+            #[cfg(debug_assertions)]
+            assert_eq!(
+                self.hash,
+                ZHash::new(self),
+                "Hash mismatch after unmaking castling"
+            );
+            return;
         }
 
-        // Queen moves
-        let mut queen_bb = self.pieces[Piece::WQ.index()] & self.sides[side_idx];
-        while queen_bb != BitBoard::EMPTY {
-            let src = queen_bb.lsb();
-            moves.extend(all_queen_moves(src, self));
-            queen_bb = queen_bb.pop_bit(src);
+        let moved = self
+            .piece_at(dest)
+            .expect("No piece on the move destination");
+        self.remove_piece(dest);
+        if move_type.is_promotion() {
+            let pawn = match self.side {
+                Colour::White => Piece::WP,
+                Colour::Black => Piece::BP,
+            };
+            self.set_piece(pawn, src);
+        } else {
+            self.set_piece(moved, src);
         }
 
-        // King moves
-        let mut king_bb = self.pieces[Piece::WK.index()] & self.sides[side_idx];
-        while king_bb != BitBoard::EMPTY {
-            let src = king_bb.lsb();
-            moves.extend(all_king_moves(src));
-            king_bb = king_bb.pop_bit(src);
+        match move_type {
+            MoveKind::EnPassant => {
+                let captured_pawn_square = dest
+                    .jump(0, -self.side.forward())
+                    .expect("Off the board en_passant");
+                self.set_piece(
+                    undo.captured.expect("No captured pawn"),
+                    captured_pawn_square,
+                );
+            }
+            _ => {
+                if let Some(captured) = undo.captured {
+                    self.set_piece(captured, dest);
+                }
+            }
         }
 
-        moves
+        // The pieces hashed themselves back, the rest of the key comes with the saved state
+        self.restore(undo);
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            self.hash,
+            ZHash::new(self),
+            "Hash mismatch after unmaking move"
+        );
     }
 
-    pub fn generate_legal_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::new();
-        let side = self.side;
-
-        let pseudo_moves = self.generate_pseudo_moves(side);
-
-        for m in pseudo_moves {
-            if self.is_pseudo_legal(m) {
-                let mut new_board = *self;
-                new_board.make_move(m);
-
-                if !new_board.is_attacked_by(new_board.king_square(side), !side) {
-                    moves.push(m);
+    /// Castling rights that depend on a rook standing on `square`
+    fn rights_lost(&self, square: Square) -> u8 {
+        let mut lost = 0;
+        for colour in [Colour::White, Colour::Black] {
+            for (rook, kingside) in self.castling_rooks[colour as usize]
+                .iter()
+                .zip([true, false])
+            {
+                if *rook == square {
+                    lost |= CastlingRights::flag(colour, kingside);
                 }
             }
         }
-
-        moves
+        lost
     }
 
-    fn is_pseudo_legal(&self, m: Move) -> bool {
-        let src = m.get_source();
-        let dest = m.get_dest();
-        let move_type = m.get_type();
-
-        let piece = self.piece_at(src).unwrap();
-        let occupied = self.sides[Colour::White as usize] | self.sides[Colour::Black as usize];
-        let opponent = self.sides[!self.side as usize];
-        let forward = piece.colour().forward();
-
-        match move_type {
-            MoveKind::Quiet => !occupied.get_bit(dest),
-            MoveKind::Capture => opponent.get_bit(dest),
-            MoveKind::DoublePush => {
-                let middle = src.jump(0, forward).unwrap();
-                !occupied.get_bit(middle) && !occupied.get_bit(dest)
-            }
-            MoveKind::EnPassant => {
-                self.en_passant == Some(dest) && opponent.get_bit(dest.jump(0, -forward).unwrap())
-            }
-            MoveKind::Castle => {
-                let (rook_sq, king_pass, king_end, inter_squares) = match (self.side, dest) {
-                    (Colour::White, d) if d == Square::from("g1") => (
-                        Square::from("h1"),
-                        Square::from("f1"),
-                        Square::from("g1"),
-                        BitBoard::WHITE_KING_CASTLE,
-                    ),
-                    (Colour::White, d) if d == Square::from("c1") => (
-                        Square::from("a1"),
-                        Square::from("d1"),
-                        Square::from("c1"),
-                        BitBoard::WHITE_QUEEN_CASTLE,
-                    ),
-                    (Colour::Black, d) if d == Square::from("g8") => (
-                        Square::from("h8"),
-                        Square::from("f8"),
-                        Square::from("g8"),
-                        BitBoard::BLACK_KING_CASTLE,
-                    ),
-                    (Colour::Black, d) if d == Square::from("c8") => (
-                        Square::from("a8"),
-                        Square::from("d8"),
-                        Square::from("c8"),
-                        BitBoard::BLACK_QUEEN_CASTLE,
-                    ),
-                    _ => return false,
-                };
-
-                let valid_rights = match (self.side, dest) {
-                    (Colour::White, d) if d == Square::from("g1") => {
-                        self.castling_rights.0 & CastlingRights::WK != 0
-                    }
-                    (Colour::White, d) if d == Square::from("c1") => {
-                        self.castling_rights.0 & CastlingRights::WQ != 0
-                    }
-                    (Colour::Black, d) if d == Square::from("g8") => {
-                        self.castling_rights.0 & CastlingRights::BK != 0
-                    }
-                    (Colour::Black, d) if d == Square::from("c8") => {
-                        self.castling_rights.0 & CastlingRights::BQ != 0
-                    }
-                    _ => return false,
-                };
-
-                valid_rights
-                    && inter_squares & occupied == BitBoard::EMPTY
-                    && !self.is_attacked_by(self.king_square(self.side), !self.side)
-                    && !self.is_attacked_by(king_pass, !self.side)
-                    && !self.is_attacked_by(king_end, !self.side)
-                    && self.piece_at(rook_sq)
-                        == Some(if piece.colour() == Colour::White {
-                            Piece::WR
-                        } else {
-                            Piece::BR
-                        })
-            }
-            MoveKind::KnightPromotion
-            | MoveKind::BishopPromotion
-            | MoveKind::RookPromotion
-            | MoveKind::QueenPromotion => !occupied.get_bit(dest),
-            MoveKind::KnightCapPromo
-            | MoveKind::BishopCapPromo
-            | MoveKind::RookCapPromo
-            | MoveKind::QueenCapPromo => opponent.get_bit(dest),
-        }
+    fn restore(&mut self, undo: Undo) {
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.halfmoves = undo.halfmoves;
+        self.hash = undo.hash;
+        self.delta = undo.delta;
     }
 
     /// Returns whether the given square is attacked by the given side or not,
@@ -389,6 +438,37 @@ impl Board {
         false
     }
 
+    /// Whether the given side has any piece other than pawns and the king
+    pub fn has_non_pawn_material(&self, colour: Colour) -> bool {
+        let pieces = self.pieces[Piece::WN.index()]
+            | self.pieces[Piece::WB.index()]
+            | self.pieces[Piece::WR.index()]
+            | self.pieces[Piece::WQ.index()];
+
+        pieces & self.sides[colour as usize] != BitBoard::EMPTY
+    }
+
+    /// Returns the pieces of both sides attacking the given square, considering only
+    /// the pieces present in `occupied`, so that x-rays can be revealed by removing pieces
+    pub fn attackers_to(&self, square: Square, occupied: BitBoard) -> BitBoard {
+        let idx = square.index();
+        let rooks = self.pieces[Piece::WR.index()] | self.pieces[Piece::WQ.index()];
+        let bishops = self.pieces[Piece::WB.index()] | self.pieces[Piece::WQ.index()];
+        let pawns = self.pieces[Piece::WP.index()];
+
+        // A white pawn attacks the square if a black pawn on the square would attack it, and vice versa
+        let white_pawns = pawn_attacks(square, Colour::Black) & pawns & self.sides[0];
+        let black_pawns = pawn_attacks(square, Colour::White) & pawns & self.sides[1];
+
+        (KNIGHT_ATTACKS[idx] & self.pieces[Piece::WN.index()]
+            | KING_ATTACKS[idx] & self.pieces[Piece::WK.index()]
+            | rook_attacks(occupied.0, idx) & rooks
+            | bishop_attacks(occupied.0, idx) & bishops
+            | white_pawns
+            | black_pawns)
+            & occupied
+    }
+
     pub fn is_draw(&self) -> bool {
         if self.halfmoves >= 100 {
             return true;
@@ -423,74 +503,263 @@ impl Board {
         king_bb.lsb()
     }
 
-    pub fn from_fen(state: &str) -> Self {
-        let fen: Vec<&str> = state.split_whitespace().take(6).collect();
+    /// Parses a FEN string, checking that it describes a position that can be played from: one
+    /// king per side, no pawns on the back ranks, castling rights backed by an unmoved king and
+    /// rook, an en passant square behind a pawn that just moved two squares, and the side that
+    /// just moved not left in check. The move counters may be omitted.
+    pub fn from_fen(state: &str) -> Result<Self, FenError> {
+        let fen: Vec<&str> = state.split_whitespace().collect();
+
+        if !(4..=6).contains(&fen.len()) {
+            return Err(FenError::FieldCount(fen.len()));
+        }
 
-        if fen.len() != 6 {
-            panic!("Invalid input FEN string");
+        let ranks: Vec<&str> = fen[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
         }
 
-        let board_layout = fen[0];
         let mut board = Board::new();
-        let (mut row, mut col): (u8, u8) = (7, 0);
-        let mut tokens = 0;
-
-        for token in board_layout.chars() {
-            match token {
-                '/' => {
-                    if tokens != 8 {
-                        panic!("Invalid number of positions in FEN");
-                    }
 
-                    row -= 1;
-                    col = 0;
-                    tokens = 0;
-                }
-                '1'..='8' => {
-                    let empty_pos = token.to_digit(10).expect("Not a number") as u8;
-                    col += empty_pos;
-                    tokens += empty_pos;
-                }
-                _ => {
-                    board.set_piece(
-                        Piece::from_fen(token),
-                        Square::from_row_col(row as usize, col as usize),
-                    );
-
-                    col += 1;
-                    tokens += 1;
+        for (i, rank) in ranks.iter().enumerate() {
+            let row = 7 - i;
+            let mut col = 0;
+
+            for token in rank.chars() {
+                match token {
+                    '1'..='8' => col += token as usize - '0' as usize,
+                    _ => {
+                        let piece = Piece::from_fen(token).ok_or(FenError::PieceChar(token))?;
+                        if col < 8 {
+                            board.set_piece(piece, Square::from_row_col(row, col));
+                        }
+                        col += 1;
+                    }
                 }
             }
+
+            if col != 8 {
+                return Err(FenError::RankLength(row + 1));
+            }
+        }
+
+        for colour in [Colour::White, Colour::Black] {
+            let kings = (board.pieces[Piece::WK.index()] & board.sides[colour as usize])
+                .count_bits() as usize;
+            if kings != 1 {
+                return Err(FenError::KingCount(colour, kings));
+            }
+        }
+
+        let back_ranks = BitBoard(0xFF000000000000FF);
+        if board.pieces[Piece::WP.index()] & back_ranks != BitBoard::EMPTY {
+            return Err(FenError::PawnOnBackRank);
         }
 
         board.side = match fen[1] {
             "w" => Colour::White,
             "b" => Colour::Black,
-            _ => unreachable!(),
+            side => return Err(FenError::Side(side.to_string())),
         };
 
-        board.castling_rights = CastlingRights::from(fen[2]);
+        if fen[2] != "-" {
+            let valid = |token: char| matches!(token.to_ascii_lowercase(), 'a'..='h' | 'k' | 'q');
+            if !fen[2].chars().all(valid) {
+                return Err(FenError::Castling(fen[2].to_string()));
+            }
+            for token in fen[2].chars() {
+                board.add_castling_right(token)?;
+            }
+        }
 
         board.en_passant = match fen[3] {
             "-" => None,
-            _ => Some(Square::from(fen[3])),
+            square => {
+                let invalid = || FenError::EnPassant(square.to_string());
+                let square = Square::parse(square).ok_or_else(invalid)?;
+                // The pawn that just moved two squares stands in front of the en passant square,
+                // which it crossed, and the square it came from is now empty
+                let forward = board.side.forward();
+                let pawn = square.jump(0, -forward).ok_or_else(invalid)?;
+                let origin = square.jump(0, forward).ok_or_else(invalid)?;
+                let pushed = match board.side {
+                    Colour::White => Piece::BP,
+                    Colour::Black => Piece::WP,
+                };
+
+                if square.row() != if board.side == Colour::White { 5 } else { 2 }
+                    || board.piece_at(pawn) != Some(pushed)
+                    || board.piece_at(square).is_some()
+                    || board.piece_at(origin).is_some()
+                {
+                    return Err(invalid());
+                }
+                Some(square)
+            }
         };
 
-        board.halfmoves = fen[4].parse::<u8>().unwrap();
+        if let Some(halfmoves) = fen.get(4) {
+            board.halfmoves = halfmoves
+                .parse()
+                .ok()
+                .filter(|&halfmoves| halfmoves <= MAX_HALFMOVES)
+                .ok_or_else(|| FenError::Counter(halfmoves.to_string()))?;
+        }
+        if let Some(fullmoves) = fen.get(5) {
+            board.fullmoves = fullmoves
+                .parse()
+                .ok()
+                .filter(|&fullmoves| fullmoves > 0)
+                .ok_or_else(|| FenError::Counter(fullmoves.to_string()))?;
+        }
+
+        if board.is_attacked_by(board.king_square(!board.side), board.side) {
+            return Err(FenError::OpponentInCheck);
+        }
+
         board.hash = ZHash::new(&board);
 
-        board
+        Ok(board)
+    }
+
+    // The board is too large to be copied just to be formatted, despite being `Copy`
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_fen(&self) -> String {
+        let mut layout = String::new();
+
+        for row in (0..8).rev() {
+            let mut empty = 0;
+            for col in 0..8 {
+                match self.piece_at(Square::from_row_col(row, col)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            layout.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        layout.push(piece.to_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+
+            if empty > 0 {
+                layout.push_str(&empty.to_string());
+            }
+            if row > 0 {
+                layout.push('/');
+            }
+        }
+
+        let side = match self.side {
+            Colour::White => "w",
+            Colour::Black => "b",
+        };
+        let en_passant = self
+            .en_passant
+            .map_or("-".to_string(), |square| square.to_string());
+
+        format!(
+            "{} {} {} {} {} {}",
+            layout,
+            side,
+            self.castling_field(),
+            en_passant,
+            self.halfmoves,
+            self.fullmoves
+        )
+    }
+
+    /// Parses one castling right of a FEN string. `K` and `Q` stand for the outermost rook on
+    /// each side of the king, as in X-FEN, while Shredder-FEN names the file of the rook
+    fn add_castling_right(&mut self, token: char) -> Result<(), FenError> {
+        let colour = if token.is_ascii_uppercase() {
+            Colour::White
+        } else {
+            Colour::Black
+        };
+        let row = if colour == Colour::White { 0 } else { 7 };
+        let king = self.king_square(colour);
+        let rook = match colour {
+            Colour::White => Piece::WR,
+            Colour::Black => Piece::BR,
+        };
+        let is_rook = |col: &usize| self.piece_at(Square::from_row_col(row, *col)) == Some(rook);
+
+        let col = match token.to_ascii_lowercase() {
+            'k' => (king.col() + 1..8).rev().find(is_rook),
+            'q' => (0..king.col()).find(is_rook),
+            file => Some(file as usize - 'a' as usize).filter(is_rook),
+        };
+        let Some(col) = col.filter(|&col| king.row() == row && col != king.col()) else {
+            return Err(FenError::CastlingRight(token));
+        };
+
+        let kingside = col > king.col();
+        self.castling_rights.0 |= CastlingRights::flag(colour, kingside);
+        self.castling_rooks[colour as usize][!kingside as usize] = Square::from_row_col(row, col);
+        Ok(())
+    }
+
+    /// Castling field of the FEN string, with the letters of standard chess unless a right
+    /// belongs to a rook that is not the outermost one on its side
+    fn castling_field(&self) -> String {
+        let mut field = String::new();
+
+        for colour in [Colour::White, Colour::Black] {
+            let rook = match colour {
+                Colour::White => Piece::WR,
+                Colour::Black => Piece::BR,
+            };
+            let king = self.king_square(colour);
+
+            for (kingside, letter) in [(true, 'K'), (false, 'Q')] {
+                if self.castling_rights.0 & CastlingRights::flag(colour, kingside) == 0 {
+                    continue;
+                }
+
+                let square = self.castling_rooks[colour as usize][!kingside as usize];
+                let mut outer = if kingside {
+                    square.col() + 1..8
+                } else {
+                    0..square.col()
+                };
+                let letter = if outer
+                    .any(|col| self.piece_at(Square::from_row_col(king.row(), col)) == Some(rook))
+                {
+                    (b'A' + square.col() as u8) as char
+                } else {
+                    letter
+                };
+
+                field.push(match colour {
+                    Colour::White => letter,
+                    Colour::Black => letter.to_ascii_lowercase(),
+                });
+            }
+        }
+
+        if field.is_empty() {
+            field.push('-');
+        }
+        field
     }
 
-    #[allow(dead_code)]
-    pub fn draw_board(&self) {
+    /// Clears the terminal and draws the board in colour, from black's side when `flipped`
+    pub fn draw_board(&self, flipped: bool) {
+        let files = if flipped {
+            "h g f e d c b a"
+        } else {
+            "a b c d e f g h"
+        };
         print!("\x1B[2J\x1B[1;1H");
-        println!("\r  a b c d e f g h\r");
+        println!("\r  {}\r", files);
         println!(" ┌────────────────┐\r");
 
-        for row in (0..8).rev() {
+        for i in 0..8 {
+            let row = if flipped { i } else { 7 - i };
             print!("{}│", row + 1);
-            for col in 0..8 {
+            for j in 0..8 {
+                let col = if flipped { 7 - j } else { j };
                 let square = Square::from_row_col(row, col);
                 let bg_colour = if (row + col) % 2 == 0 {
                     "\x1b[48;2;240;217;181m"
@@ -514,7 +783,30 @@ impl Board {
     }
 }
 
-// For debugging
+/// Squares the king and the rook end on when the king on `king` castles with the rook on `rook`
+pub(super) fn castling_squares(king: Square, rook: Square) -> (Square, Square) {
+    if rook.col() > king.col() {
+        (
+            Square::from_row_col(king.row(), 6),
+            Square::from_row_col(king.row(), 5),
+        )
+    } else {
+        (
+            Square::from_row_col(king.row(), 2),
+            Square::from_row_col(king.row(), 3),
+        )
+    }
+}
+
+/// Squares attacked by a pawn of the given colour placed on `square`
+pub(super) fn pawn_attacks(square: Square, colour: Colour) -> BitBoard {
+    [-1, 1]
+        .iter()
+        .filter_map(|&delta| square.jump(delta, colour.forward()))
+        .fold(BitBoard::EMPTY, |attacks, dest| attacks.set_bit(dest))
+}
+
+/// Plain text board with FEN piece letters, white pieces in uppercase
 impl std::fmt::Display for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "  a b c d e f g h")?;
@@ -525,7 +817,7 @@ impl std::fmt::Display for Board {
             for col in 0..8 {
                 let square = Square::from_row_col(row, col);
                 match self.piece_map[square.index()] {
-                    Some(piece) => write!(f, "{} ", piece)?,
+                    Some(piece) => write!(f, "{} ", piece.to_char())?,
                     None => write!(f, "  ")?,
                 }
             }
@@ -536,3 +828,115 @@ impl std::fmt::Display for Board {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fen_validation() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 12 40",
+            "rr2k3/8/8/8/8/8/8/RR2K2R w KBb - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 150 200",
+        ] {
+            assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+        }
+        // Shredder-FEN rights of the outermost rooks are written as in X-FEN
+        assert_eq!(
+            Board::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+                .unwrap()
+                .to_fen(),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+        assert_eq!(
+            Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - -")
+                .unwrap()
+                .to_fen(),
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1"
+        );
+
+        // The largest clock accepted still has room for the next move
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 150 200").unwrap();
+        board.make_move(board.generate_legal_moves()[0]);
+        assert_eq!(board.halfmoves, 151);
+
+        for (fen, err) in [
+            ("4k3/8/8/8/8/8/8/4K3 w", FenError::FieldCount(2)),
+            ("4k3/8/8/8/8/8/4K3 w - -", FenError::RankCount(7)),
+            ("4k3/8/8/8/8/8/8/4K4 w - -", FenError::RankLength(1)),
+            ("4k3/8/8/8/8/8/8/4X3 w - -", FenError::PieceChar('X')),
+            (
+                "4k3/8/8/8/8/8/8/8 w - -",
+                FenError::KingCount(Colour::White, 0),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/2K1K3 w - -",
+                FenError::KingCount(Colour::White, 2),
+            ),
+            ("P3k3/8/8/8/8/8/8/4K3 w - -", FenError::PawnOnBackRank),
+            ("4k3/8/8/8/8/8/8/4K3 x - -", FenError::Side("x".to_string())),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w KX -",
+                FenError::Castling("KX".to_string()),
+            ),
+            ("4k3/8/8/8/8/8/8/4K2R w Q -", FenError::CastlingRight('Q')),
+            ("4k3/8/8/8/8/8/8/4K2R w A -", FenError::CastlingRight('A')),
+            (
+                "4k3/8/8/8/4P3/8/8/4K3 w - e3",
+                FenError::EnPassant("e3".to_string()),
+            ),
+            (
+                "4k3/8/8/8/4P3/8/8/4K3 b - e6",
+                FenError::EnPassant("e6".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+                FenError::Counter("x".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 255 200",
+                FenError::Counter("255".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 151 200",
+                FenError::Counter("151".to_string()),
+            ),
+            ("4k3/8/8/8/8/8/4R3/4K3 w - -", FenError::OpponentInCheck),
+        ] {
+            assert_eq!(Board::from_fen(fen).unwrap_err(), err, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_unmake_move() {
+        fn walk(board: &mut Board, depth: usize) {
+            let (fen, hash) = (board.to_fen(), board.hash);
+            for m in board.generate_legal_moves() {
+                let undo = board.make_move(m);
+                if depth > 1 {
+                    walk(board, depth - 1);
+                }
+                board.unmake_move(m, undo);
+                assert_eq!(board.to_fen(), fen, "{}", m);
+                assert_eq!(board.hash, hash, "{}", m);
+            }
+
+            let undo = board.make_null_move();
+            board.unmake_null_move(undo);
+            assert_eq!(board.to_fen(), fen);
+            assert_eq!(board.hash, hash);
+        }
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "1r1k2r1/8/8/8/8/8/8/1R1K2R1 w KQkq - 0 1",
+        ] {
+            walk(&mut Board::from_fen(fen).unwrap(), 3);
+        }
+    }
+}
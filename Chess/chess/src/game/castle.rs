@@ -1,6 +1,9 @@
+use super::piece::Colour;
+
 /// Castling rights struct
 /// Implemented through a flag bit vector. This allows for fast castle update without needing
-/// bitboard lookups.
+/// bitboard lookups. The squares of the castling rooks, which differ in Chess960, are kept by
+/// the board.
 ///
 ///  WK | WQ | BK | BQ  --> only using least significant 8 bits
 ///  08   04   02   01
@@ -18,31 +21,13 @@ impl CastlingRights {
         self.0 as usize
     }
 
-    pub fn from(rights: &str) -> Self {
-        let mut right = Self::NONE;
-
-        if rights == "-" {
-            return right;
+    /// Right of `colour` to castle on the king side, or on the queen side
+    pub const fn flag(colour: Colour, kingside: bool) -> u8 {
+        match (colour, kingside) {
+            (Colour::White, true) => Self::WK,
+            (Colour::White, false) => Self::WQ,
+            (Colour::Black, true) => Self::BK,
+            (Colour::Black, false) => Self::BQ,
         }
-
-        for token in rights.chars() {
-            match token {
-                'K' => {
-                    right.0 |= Self::WK;
-                }
-                'Q' => {
-                    right.0 |= Self::WQ;
-                }
-                'k' => {
-                    right.0 |= Self::BK;
-                }
-                'q' => {
-                    right.0 |= Self::BQ;
-                }
-                _ => panic!("Invalid CastlingRights in FEN"),
-            }
-        }
-
-        right
     }
 }
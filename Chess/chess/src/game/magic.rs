@@ -0,0 +1,253 @@
+use crate::game::bitboard::BitBoard;
+use std::sync::OnceLock;
+
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// Multipliers mapping every blocker configuration of a rook square to a slot of its attack
+/// table, found by trying sparse random numbers until no two configurations with different
+/// attacks collide
+#[rustfmt::skip]
+const ROOK_MAGICS: [u64; 64] = [
+    0x0080008040002018, 0x0040100040002001, 0x09000d0010200040, 0x8080080010008004,
+    0xa280080002340080, 0x2500050024000208, 0x0280010000800200, 0x1100144380220100,
+    0xa92080048c204002, 0x0802804003200080, 0x0108802000100089, 0x8060800800801002,
+    0xa002000822000410, 0x950a001002000824, 0x1202000408010200, 0x02950008408a0100,
+    0x40a0a18000814000, 0x0080848020004011, 0x0800828010002000, 0x0500420010200a00,
+    0x0008818004000802, 0x0000808004000200, 0x0000040001020810, 0x0081120000442081,
+    0x0522400180002090, 0x2800200040401000, 0x402004a180100481, 0x0880420200200810,
+    0x000a040080800800, 0x080a000404001020, 0x0000010400820810, 0x4000d08200004c09,
+    0x0100804000800020, 0xcc01028026004200, 0x0081002001004010, 0x0000800800801000,
+    0x0009800401802800, 0x0002000902000410, 0x0000180144001022, 0x00008100c6002884,
+    0x1200800040008024, 0x2000200050044000, 0x0090882200420010, 0x6a1810200a020040,
+    0x0480040008008080, 0x0206001108160014, 0xc010040200010100, 0x000001018046000c,
+    0x00b0400880042080, 0x0040002010080220, 0x0020004228110100, 0x0150000804004140,
+    0x0028008004000980, 0x0801000804000300, 0x2c80210208900400, 0x0000040130408200,
+    0x4001409100258001, 0x0005004002883021, 0x019041e003001019, 0x8000200900041001,
+    0x4021001002040801, 0x0011000400080201, 0x1600010210408804, 0x2010810084003042,
+];
+
+#[rustfmt::skip]
+const BISHOP_MAGICS: [u64; 64] = [
+    0x0032482800818200, 0x0819022820450000, 0x4242108204880008, 0x8044404080410224,
+    0x1407104000080100, 0x1b01100210004000, 0x0b0c0888841010c1, 0x08a0108201104020,
+    0x00003888654c0410, 0x0010220202520a00, 0x4003048400820001, 0x1007022082000002,
+    0x3c22040420040a18, 0x3440508820080030, 0x1204208090082100, 0x0000930501012000,
+    0x0010842002d00100, 0x0202000404484208, 0x0008000100410602, 0x0088000404200800,
+    0xa004100202020232, 0x0004200100a01002, 0x0201000208020200, 0x2022010022020200,
+    0x80100410100410b0, 0x3010704848011100, 0x2000380010048320, 0x8068080000202120,
+    0x0001010080104000, 0x4808004120806000, 0x5000821000823008, 0x0004010000504224,
+    0x0088044010040808, 0x0812022000032814, 0xa000280800110202, 0x2882200802010105,
+    0x0508082400024100, 0x8001010200030800, 0x4001260e00019820, 0x0c02040308507180,
+    0x0880884441009000, 0x20a84202a0011000, 0x00010410820c1000, 0x4000002018000108,
+    0x0080091124004a00, 0x0409010302010700, 0x0088128802040040, 0x1801010408801100,
+    0x0184010190110800, 0x0000404208210000, 0x0000120908880000, 0x9006180020884020,
+    0x900000400488410a, 0x0410218401020100, 0x0004d00428008100, 0x0002021404008400,
+    0x0911410150222026, 0x0300202088041040, 0x0100a06124122800, 0x001c042000420200,
+    0x0004018208030400, 0x0044040405080200, 0x040820200402b680, 0x0204200411020410,
+];
+
+/// Attack table lookup for the slider on one square
+#[derive(Clone, Copy, Debug, Default)]
+struct Magic {
+    /// Squares whose occupancy changes the attacks, board edges excluded
+    mask: u64,
+    #[cfg_attr(target_feature = "bmi2", allow(dead_code))]
+    magic: u64,
+    #[cfg_attr(target_feature = "bmi2", allow(dead_code))]
+    shift: u32,
+    /// Start of the square attacks in the shared table
+    offset: usize,
+}
+
+impl Magic {
+    /// Slot of the attacks for the given occupancy. With BMI2 the relevant occupancy bits are
+    /// extracted directly, which gives the same table size as the magic multiplication
+    #[inline(always)]
+    fn index(&self, occupied: u64) -> usize {
+        #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+        {
+            // SAFETY: only compiled in when the target supports BMI2
+            self.offset + unsafe { std::arch::x86_64::_pext_u64(occupied, self.mask) } as usize
+        }
+
+        #[cfg(not(all(target_arch = "x86_64", target_feature = "bmi2")))]
+        {
+            self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+        }
+    }
+}
+
+/// Precomputed rook and bishop attacks for every square and blocker configuration
+struct SliderTables {
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    attacks: Vec<BitBoard>,
+}
+
+impl SliderTables {
+    fn new() -> Self {
+        let mut attacks = Vec::new();
+        let rook = Self::fill(&mut attacks, &ROOK_DIRECTIONS, &ROOK_MAGICS);
+        let bishop = Self::fill(&mut attacks, &BISHOP_DIRECTIONS, &BISHOP_MAGICS);
+
+        Self {
+            rook,
+            bishop,
+            attacks,
+        }
+    }
+
+    /// Appends the attacks of one slider type to the table, enumerating every subset of each
+    /// square mask with the carry rippler trick
+    fn fill(
+        attacks: &mut Vec<BitBoard>,
+        directions: &[(i8, i8)],
+        magics: &[u64; 64],
+    ) -> [Magic; 64] {
+        let mut entries = [Magic::default(); 64];
+
+        for (square, entry) in entries.iter_mut().enumerate() {
+            let mask = relevant_mask(square, directions);
+            let bits = mask.count_ones();
+            *entry = Magic {
+                mask,
+                magic: magics[square],
+                shift: 64 - bits,
+                offset: attacks.len(),
+            };
+            attacks.resize(attacks.len() + (1 << bits), BitBoard::EMPTY);
+
+            let mut subset = 0u64;
+            loop {
+                attacks[entry.index(subset)] = slide(square, subset, directions);
+                subset = subset.wrapping_sub(mask) & mask;
+                if subset == 0 {
+                    break;
+                }
+            }
+        }
+
+        entries
+    }
+}
+
+fn tables() -> &'static SliderTables {
+    static TABLES: OnceLock<SliderTables> = OnceLock::new();
+    TABLES.get_or_init(SliderTables::new)
+}
+
+/// Walks every direction from the square until the edge or the first occupied square, which is
+/// included in the attacks
+fn slide(square: usize, occupied: u64, directions: &[(i8, i8)]) -> BitBoard {
+    let mut attacks = 0;
+
+    for &(file_delta, rank_delta) in directions {
+        let (mut file, mut rank) = ((square % 8) as i8, (square / 8) as i8);
+        loop {
+            file += file_delta;
+            rank += rank_delta;
+            if !(0..8).contains(&file) || !(0..8).contains(&rank) {
+                break;
+            }
+
+            let bit = 1 << (rank * 8 + file);
+            attacks |= bit;
+            if occupied & bit != 0 {
+                break;
+            }
+        }
+    }
+
+    BitBoard(attacks)
+}
+
+/// Squares a blocker may stand on to change the attacks, the last square of every ray never
+/// blocks anything
+fn relevant_mask(square: usize, directions: &[(i8, i8)]) -> u64 {
+    let mut mask = 0;
+
+    for &(file_delta, rank_delta) in directions {
+        let (mut file, mut rank) = ((square % 8) as i8, (square / 8) as i8);
+        loop {
+            let (next_file, next_rank) = (file + 2 * file_delta, rank + 2 * rank_delta);
+            if !(0..8).contains(&next_file) || !(0..8).contains(&next_rank) {
+                break;
+            }
+
+            file += file_delta;
+            rank += rank_delta;
+            mask |= 1 << (rank * 8 + file);
+        }
+    }
+
+    mask
+}
+
+#[inline(always)]
+pub fn rook_attacks(occ: u64, sq: usize) -> BitBoard {
+    let tables = tables();
+    tables.attacks[tables.rook[sq].index(occ)]
+}
+
+#[inline(always)]
+pub fn bishop_attacks(occ: u64, sq: usize) -> BitBoard {
+    let tables = tables();
+    tables.attacks[tables.bishop[sq].index(occ)]
+}
+
+/// Squares strictly between two squares sharing a rank, file or diagonal, empty otherwise
+pub fn between(a: usize, b: usize) -> BitBoard {
+    let (a_bb, b_bb) = (1u64 << a, 1u64 << b);
+
+    if rook_attacks(0, a).0 & b_bb != 0 {
+        rook_attacks(b_bb, a) & rook_attacks(a_bb, b)
+    } else if bishop_attacks(0, a).0 & b_bb != 0 {
+        bishop_attacks(b_bb, a) & bishop_attacks(a_bb, b)
+    } else {
+        BitBoard::EMPTY
+    }
+}
+
+/// The whole rank, file or diagonal going through two squares, empty if they are not aligned
+pub fn line(a: usize, b: usize) -> BitBoard {
+    let ends = BitBoard((1u64 << a) | (1u64 << b));
+
+    if rook_attacks(0, a).0 & (1u64 << b) != 0 {
+        rook_attacks(0, a) & rook_attacks(0, b) | ends
+    } else if bishop_attacks(0, a).0 & (1u64 << b) != 0 {
+        bishop_attacks(0, a) & bishop_attacks(0, b) | ends
+    } else {
+        BitBoard::EMPTY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slider_attacks() {
+        let mut seed = 0x9E37_79B9_7F4A_7C15u64;
+        let mut random = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for _ in 0..2000 {
+            let occupied = random() & random();
+            for square in 0..64 {
+                assert_eq!(
+                    rook_attacks(occupied, square),
+                    slide(square, occupied, &ROOK_DIRECTIONS)
+                );
+                assert_eq!(
+                    bishop_attacks(occupied, square),
+                    slide(square, occupied, &BISHOP_DIRECTIONS)
+                );
+            }
+        }
+    }
+}
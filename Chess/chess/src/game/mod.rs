@@ -2,10 +2,15 @@ pub mod bitboard;
 pub mod board;
 pub mod castle;
 pub mod constants;
+pub mod magic;
+pub mod movegen;
 pub mod moves;
-pub mod perft;
+#[cfg(test)]
+mod perft;
+pub mod pgn;
 pub mod piece;
 pub mod position;
+pub mod san;
 pub mod square;
 pub mod tt;
 pub mod uci;
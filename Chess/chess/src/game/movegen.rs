@@ -0,0 +1,302 @@
+use super::{
+    bitboard::BitBoard,
+    board::{castling_squares, pawn_attacks, Board},
+    castle::CastlingRights,
+    constants::{KING_ATTACKS, KNIGHT_ATTACKS},
+    magic::{between, bishop_attacks, line, rook_attacks},
+    moves::{Move, MoveKind, MoveList},
+    piece::{Colour, Piece},
+    square::Square,
+};
+
+const PROMOTIONS: [MoveKind; 4] = [
+    MoveKind::QueenPromotion,
+    MoveKind::RookPromotion,
+    MoveKind::BishopPromotion,
+    MoveKind::KnightPromotion,
+];
+const CAPTURE_PROMOTIONS: [MoveKind; 4] = [
+    MoveKind::QueenCapPromo,
+    MoveKind::RookCapPromo,
+    MoveKind::BishopCapPromo,
+    MoveKind::KnightCapPromo,
+];
+
+impl Board {
+    /// Every legal move of the side to move
+    pub fn generate_legal_moves(&self) -> MoveList {
+        self.generate::<true, true>()
+    }
+
+    /// Legal captures and promotions, the moves quiescence search looks at
+    pub fn generate_captures(&self) -> MoveList {
+        self.generate::<true, false>()
+    }
+
+    /// Legal moves that neither capture nor promote, castling included
+    pub fn generate_quiets(&self) -> MoveList {
+        self.generate::<false, true>()
+    }
+
+    /// Generates legal moves directly instead of filtering pseudo legal ones: the king may only
+    /// step on unattacked squares, other pieces must resolve a check by capturing or blocking the
+    /// checker, and pinned pieces may only move along the line of their pin
+    fn generate<const CAPTURES: bool, const QUIETS: bool>(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        let side = self.side;
+        let own = self.sides[side as usize];
+        let enemy = self.sides[!side as usize];
+        let occupied = own | enemy;
+        let king = self.king_square(side);
+
+        let mut targets = BitBoard::EMPTY;
+        if CAPTURES {
+            targets |= enemy;
+        }
+        if QUIETS {
+            targets |= !occupied;
+        }
+
+        // The king is removed from the board so that it cannot hide behind itself from a slider
+        let without_king = occupied ^ king.to_board();
+        let mut king_moves = KING_ATTACKS[king.index()] & targets;
+        while king_moves != BitBoard::EMPTY {
+            let dest = king_moves.lsb();
+            if self.attackers_to(dest, without_king) & enemy == BitBoard::EMPTY {
+                moves.push(Move::new(king, dest, capture_kind(enemy, dest)));
+            }
+            king_moves = king_moves.pop_bit(dest);
+        }
+
+        let checkers = self.attackers_to(king, occupied) & enemy;
+        if checkers.count_bits() > 1 {
+            return moves;
+        }
+
+        let check_mask = if checkers == BitBoard::EMPTY {
+            !BitBoard::EMPTY
+        } else {
+            between(king.index(), checkers.lsb().index()) | checkers
+        };
+        let pinned = self.pinned(king, own, enemy, occupied);
+        let allowed = |src: Square| {
+            if pinned.get_bit(src) {
+                check_mask & line(king.index(), src.index())
+            } else {
+                check_mask
+            }
+        };
+
+        self.pawn_moves::<CAPTURES, QUIETS>(&mut moves, &allowed);
+
+        for piece in [Piece::WN, Piece::WB, Piece::WR, Piece::WQ] {
+            let mut piece_bb = self.pieces[piece.index()] & own;
+            while piece_bb != BitBoard::EMPTY {
+                let src = piece_bb.lsb();
+                let attacks = match piece {
+                    Piece::WN => KNIGHT_ATTACKS[src.index()],
+                    Piece::WB => bishop_attacks(occupied.0, src.index()),
+                    Piece::WR => rook_attacks(occupied.0, src.index()),
+                    _ => {
+                        bishop_attacks(occupied.0, src.index())
+                            | rook_attacks(occupied.0, src.index())
+                    }
+                };
+
+                let mut dests = attacks & targets & allowed(src);
+                while dests != BitBoard::EMPTY {
+                    let dest = dests.lsb();
+                    moves.push(Move::new(src, dest, capture_kind(enemy, dest)));
+                    dests = dests.pop_bit(dest);
+                }
+                piece_bb = piece_bb.pop_bit(src);
+            }
+        }
+
+        if QUIETS && checkers == BitBoard::EMPTY {
+            self.castling_moves(&mut moves, king, occupied);
+        }
+
+        moves
+    }
+
+    /// Own pieces standing alone between the king and an enemy slider aiming at it
+    fn pinned(&self, king: Square, own: BitBoard, enemy: BitBoard, occupied: BitBoard) -> BitBoard {
+        let queens = self.pieces[Piece::WQ.index()];
+        let rooks = self.pieces[Piece::WR.index()] | queens;
+        let bishops = self.pieces[Piece::WB.index()] | queens;
+
+        let mut snipers = (rook_attacks(enemy.0, king.index()) & rooks
+            | bishop_attacks(enemy.0, king.index()) & bishops)
+            & enemy;
+        let mut pinned = BitBoard::EMPTY;
+
+        while snipers != BitBoard::EMPTY {
+            let sniper = snipers.lsb();
+            let blockers = between(king.index(), sniper.index()) & occupied;
+            if blockers.count_bits() == 1 && blockers & own != BitBoard::EMPTY {
+                pinned |= blockers;
+            }
+            snipers = snipers.pop_bit(sniper);
+        }
+
+        pinned
+    }
+
+    fn pawn_moves<const CAPTURES: bool, const QUIETS: bool>(
+        &self,
+        moves: &mut MoveList,
+        allowed: &impl Fn(Square) -> BitBoard,
+    ) {
+        let side = self.side;
+        let forward = side.forward();
+        let enemy = self.sides[!side as usize];
+        let occupied = self.sides[0] | self.sides[1];
+        let start_rank = BitBoard::START_RANKS[side as usize];
+        let promo_rank = BitBoard::PROMO_RANKS[side as usize];
+
+        let mut pawn_bb = self.pieces[Piece::WP.index()] & self.sides[side as usize];
+        while pawn_bb != BitBoard::EMPTY {
+            let src = pawn_bb.lsb();
+            pawn_bb = pawn_bb.pop_bit(src);
+            let allowed = allowed(src);
+
+            if let Some(dest) = src.jump(0, forward).filter(|&dest| !occupied.get_bit(dest)) {
+                if allowed.get_bit(dest) {
+                    if promo_rank.get_bit(dest) {
+                        if CAPTURES {
+                            for kind in PROMOTIONS {
+                                moves.push(Move::new(src, dest, kind));
+                            }
+                        }
+                    } else if QUIETS {
+                        moves.push(Move::new(src, dest, MoveKind::Quiet));
+                    }
+                }
+
+                if QUIETS && start_rank.get_bit(src) {
+                    let double = src.jump(0, 2 * forward).expect("Pawn on its start rank");
+                    if !occupied.get_bit(double) && allowed.get_bit(double) {
+                        moves.push(Move::new(src, double, MoveKind::DoublePush));
+                    }
+                }
+            }
+
+            if !CAPTURES {
+                continue;
+            }
+
+            let attacks = pawn_attacks(src, side);
+            let mut captures = attacks & enemy & allowed;
+            while captures != BitBoard::EMPTY {
+                let dest = captures.lsb();
+                if promo_rank.get_bit(dest) {
+                    for kind in CAPTURE_PROMOTIONS {
+                        moves.push(Move::new(src, dest, kind));
+                    }
+                } else {
+                    moves.push(Move::new(src, dest, MoveKind::Capture));
+                }
+                captures = captures.pop_bit(dest);
+            }
+
+            // En passant removes two pawns from the same rank at once, which can expose the king
+            // in ways the pin masks do not cover, so it is simply tried out
+            if let Some(ep) = self.en_passant.filter(|&ep| attacks.get_bit(ep)) {
+                let m = Move::new(src, ep, MoveKind::EnPassant);
+                let mut next = *self;
+                next.make_move(m);
+                if !next.is_attacked_by(next.king_square(side), !side) {
+                    moves.push(m);
+                }
+            }
+        }
+    }
+
+    /// Castling moves, encoded as the king taking its own rook. Every square either piece goes
+    /// through has to be empty, and the king may not pass an attacked square, which is checked
+    /// without both pieces on the board since in Chess960 the rook may be shielding the king
+    fn castling_moves(&self, moves: &mut MoveList, king: Square, occupied: BitBoard) {
+        let side = self.side;
+        let enemy = self.sides[!side as usize];
+        let rook = match side {
+            Colour::White => Piece::WR,
+            Colour::Black => Piece::BR,
+        };
+
+        for (kingside, rook_sq) in [true, false]
+            .into_iter()
+            .zip(self.castling_rooks[side as usize])
+        {
+            if self.castling_rights.0 & CastlingRights::flag(side, kingside) == 0
+                || self.piece_at(rook_sq) != Some(rook)
+            {
+                continue;
+            }
+
+            let (king_dest, rook_dest) = castling_squares(king, rook_sq);
+            let king_path = between(king.index(), king_dest.index()) | king_dest.to_board();
+            let rook_path = between(rook_sq.index(), rook_dest.index()) | rook_dest.to_board();
+            let others = occupied ^ king.to_board() ^ rook_sq.to_board();
+            if (king_path | rook_path) & others != BitBoard::EMPTY {
+                continue;
+            }
+
+            let mut path = king_path;
+            let mut safe = true;
+            while safe && path != BitBoard::EMPTY {
+                let square = path.lsb();
+                safe = self.attackers_to(square, others) & enemy == BitBoard::EMPTY;
+                path = path.pop_bit(square);
+            }
+
+            if safe {
+                moves.push(Move::new(king, rook_sq, MoveKind::Castle));
+            }
+        }
+    }
+}
+
+fn capture_kind(enemy: BitBoard, dest: Square) -> MoveKind {
+    if enemy.get_bit(dest) {
+        MoveKind::Capture
+    } else {
+        MoveKind::Quiet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_quiet_split() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4k3/8/8/2KPp2r/8/8/8/8 w - e6 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let legal = board.generate_legal_moves();
+            let captures = board.generate_captures();
+            let quiets = board.generate_quiets();
+
+            assert_eq!(captures.len() + quiets.len(), legal.len(), "{}", fen);
+            assert!(captures
+                .iter()
+                .all(|m| legal.contains(m)
+                    && (m.get_type().is_capture() || m.get_type().is_promotion())));
+            assert!(quiets.iter().all(|m| legal.contains(m)
+                && !m.get_type().is_capture()
+                && !m.get_type().is_promotion()));
+        }
+
+        // The en passant capture would leave the king in check along the rank
+        let board = Board::from_fen("4k3/8/8/2KPp2r/8/8/8/8 w - e6 0 1").unwrap();
+        assert!(board
+            .generate_captures()
+            .iter()
+            .all(|m| m.get_type() != MoveKind::EnPassant));
+    }
+}
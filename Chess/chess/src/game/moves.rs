@@ -1,23 +1,6 @@
 use crate::game::square::Square;
 
-use super::{
-    bitboard::BitBoard,
-    board::Board,
-    piece::{Colour, Piece},
-};
-
-#[rustfmt::skip]
-const KNIGHT_OFFSETS: [(i8, i8); 8] = [
-    (2, 1), (2, -1), (-2, 1), (-2, -1),
-    (1, 2), (1, -2), (-1, 2), (-1, -2),
-];
-#[rustfmt::skip]
-const KING_OFFSETS: [(i8, i8); 8] = [
-    (1, 0), (1, 1), (1, -1), (0, 1),
-    (0, -1), (-1, 0), (-1, 1), (-1, -1),
-];
-const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
-const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+use super::piece::{Colour, Piece};
 
 /// A move needs 16 bits to be stored, the information is contained
 /// in the following way:
@@ -67,12 +50,29 @@ impl Move {
             _ => unreachable!(),
         }
     }
+
+    /// UCI notation of the move, in which Chess960 castling is written as the king taking its
+    /// own rook. Standard notation, used otherwise, moves the king two squares instead
+    pub fn to_uci(self, chess960: bool) -> String {
+        if chess960 && self.get_type() == MoveKind::Castle {
+            format!("{}{}", self.get_source(), self.get_dest())
+        } else {
+            self.to_string()
+        }
+    }
 }
 
+/// Standard UCI notation, castling moves are encoded as the king taking its own rook but are
+/// written with the square the king ends on
 impl std::fmt::Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = format!("{}{}", self.get_source(), self.get_dest());
+        let (src, mut dest) = (self.get_source(), self.get_dest());
         let move_type = self.get_type();
+        if move_type == MoveKind::Castle {
+            let col = if dest.col() > src.col() { 6 } else { 2 };
+            dest = Square::from_row_col(src.row(), col);
+        }
+        let s = format!("{}{}", src, dest);
 
         if move_type.is_promotion() {
             write!(
@@ -133,120 +133,59 @@ impl MoveKind {
     }
 }
 
-pub fn all_pawn_moves(src: Square, piece: Piece, board: &Board) -> Vec<Move> {
-    let mut moves = Vec::with_capacity(4);
-    let forward = piece.colour().forward();
+/// Upper bound on the number of legal moves of any position, which is 218
+pub const MAX_MOVES: usize = 256;
 
-    let start_rank = BitBoard::START_RANKS[piece.colour() as usize];
-    let promo_rank = BitBoard::PROMO_RANKS[piece.colour() as usize];
-    let opponent = board.sides[!piece.colour() as usize];
+/// Fixed capacity list of moves living on the stack, so that generating moves never allocates
+#[derive(Clone, Copy, Debug)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
 
-    if let Some(dest) = src.jump(0, forward) {
-        if promo_rank.get_bit(dest) {
-            moves.push(Move::new(src, dest, MoveKind::QueenPromotion));
-            moves.push(Move::new(src, dest, MoveKind::RookPromotion));
-            moves.push(Move::new(src, dest, MoveKind::BishopPromotion));
-            moves.push(Move::new(src, dest, MoveKind::KnightPromotion));
-        } else {
-            moves.push(Move::new(src, dest, MoveKind::Quiet));
+impl MoveList {
+    pub fn new() -> Self {
+        Self {
+            moves: [Move::default(); MAX_MOVES],
+            len: 0,
         }
     }
 
-    if start_rank.get_bit(src) {
-        moves.push(Move::new(
-            src,
-            src.jump(0, 2 * forward).unwrap(),
-            MoveKind::DoublePush,
-        ));
+    #[inline]
+    pub fn push(&mut self, m: Move) {
+        self.moves[self.len] = m;
+        self.len += 1;
     }
-
-    for delta in [(-1, forward), (1, forward)] {
-        if let Some(dest) = src.jump(delta.0, delta.1) {
-            if opponent.get_bit(dest) {
-                if promo_rank.get_bit(dest) {
-                    moves.push(Move::new(src, dest, MoveKind::QueenCapPromo));
-                    moves.push(Move::new(src, dest, MoveKind::RookCapPromo));
-                    moves.push(Move::new(src, dest, MoveKind::BishopCapPromo));
-                    moves.push(Move::new(src, dest, MoveKind::KnightCapPromo));
-                } else {
-                    moves.push(Move::new(src, dest, MoveKind::Capture));
-                }
-            } else if board.en_passant == Some(dest) {
-                let ep_target = dest.jump(0, -forward).expect("Invalid en passant target");
-                if opponent.get_bit(ep_target) {
-                    moves.push(Move::new(src, dest, MoveKind::EnPassant));
-                }
-            }
-        }
-    }
-    moves
 }
 
-pub fn all_knight_moves(src: Square) -> Vec<Move> {
-    let mut moves = Vec::with_capacity(8);
-
-    for &(file_delta, rank_delta) in &KNIGHT_OFFSETS {
-        if let Some(dest) = src.jump(file_delta, rank_delta) {
-            moves.push(Move::new(src, dest, MoveKind::Quiet));
-            moves.push(Move::new(src, dest, MoveKind::Capture));
-        }
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
     }
-
-    moves
 }
 
-fn sliding_moves(src: Square, board: &Board, directions: &[(i8, i8)]) -> Vec<Move> {
-    let mut moves = Vec::with_capacity(8);
-    for &(file_delta, rank_delta) in directions {
-        let mut dest = src;
-        while let Some(next) = dest.jump(file_delta, rank_delta) {
-            dest = next;
-            if board.piece_at(dest).is_some() {
-                moves.push(Move::new(src, dest, MoveKind::Capture));
-                break;
-            }
-            moves.push(Move::new(src, dest, MoveKind::Quiet));
-        }
+impl std::ops::Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
     }
-    moves
 }
 
-pub fn all_bishop_moves(src: Square, board: &Board) -> Vec<Move> {
-    sliding_moves(src, board, &BISHOP_DIRECTIONS)
-}
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Move, MAX_MOVES>>;
 
-pub fn all_rook_moves(src: Square, board: &Board) -> Vec<Move> {
-    sliding_moves(src, board, &ROOK_DIRECTIONS)
-}
-
-pub fn all_queen_moves(src: Square, board: &Board) -> Vec<Move> {
-    sliding_moves(src, board, &BISHOP_DIRECTIONS)
-        .into_iter()
-        .chain(sliding_moves(src, board, &ROOK_DIRECTIONS))
-        .collect()
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
 }
 
-pub fn all_king_moves(src: Square) -> Vec<Move> {
-    let mut moves = Vec::with_capacity(4);
-    for &(file_delta, rank_delta) in &KING_OFFSETS {
-        if let Some(dest) = src.jump(file_delta, rank_delta) {
-            moves.push(Move::new(src, dest, MoveKind::Quiet));
-            moves.push(Move::new(src, dest, MoveKind::Capture));
-        }
-    }
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
 
-    if src.to_board() & BitBoard::KING_START_POS != BitBoard::EMPTY {
-        moves.push(Move::new(
-            src,
-            Square::from_row_col(src.row(), 6),
-            MoveKind::Castle,
-        ));
-        moves.push(Move::new(
-            src,
-            Square::from_row_col(src.row(), 2),
-            MoveKind::Castle,
-        ));
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
-
-    moves
 }
@@ -6,32 +6,9 @@ use crate::game::board::Board;
 use crate::game::moves::Move;
 
 pub const BULK: bool = true;
-pub const NO_BULK: bool = false;
 pub const NUM_THREADS: usize = 16;
 
-pub fn perft_with_moves(board: &mut Board, depth: usize) -> u64 {
-    if depth == 0 {
-        return 1;
-    }
-    let moves = board.generate_legal_moves();
-    let mut total = 0;
-    if depth == 1 {
-        for m in &moves {
-            let mut new_board = *board;
-            new_board.make_move(*m);
-            let count = perft_with_moves(&mut new_board, depth - 1);
-            println!("{}: {}", m, count);
-            total += count;
-        }
-    } else {
-        for m in &moves {
-            let mut new_board = *board;
-            new_board.make_move(*m);
-            total += perft_with_moves(&mut new_board, depth - 1);
-        }
-    }
-    total
-}
+type PerftResult = (Move, u64, Vec<u64>);
 
 impl Board {
     fn perft_driver<const BULK_COUNT: bool>(
@@ -55,9 +32,9 @@ impl Board {
 
         let mut nodes = 0;
         for m in moves {
-            let mut new_board = *self;
-            new_board.make_move(m);
-            nodes += new_board.perft_driver::<BULK_COUNT>(depth - 1, level_counts);
+            let undo = self.make_move(m);
+            nodes += self.perft_driver::<BULK_COUNT>(depth - 1, level_counts);
+            self.unmake_move(m, undo);
         }
         nodes
     }
@@ -74,10 +51,7 @@ impl Board {
 
         let moves_per_thread = moves.len().div_ceil(NUM_THREADS);
 
-        let (tx, rx): (
-            Sender<(Move, u64, Vec<u64>)>,
-            Receiver<(Move, u64, Vec<u64>)>,
-        ) = channel();
+        let (tx, rx): (Sender<PerftResult>, Receiver<PerftResult>) = channel();
         let mut handles = Vec::new();
         let mut total_level_counts = vec![0u64; depth];
 
@@ -163,7 +137,7 @@ mod tests {
 
     #[test]
     fn test_perft_suite() {
-        const PERFT_SUITE: [(&str, &str, u64, usize); 16] = [
+        const PERFT_SUITE: [(&str, &str, u64, usize); 19] = [
             (
                 "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
                 "Startpos",
@@ -255,6 +229,24 @@ mod tests {
                 23_527,
                 4,
             ),
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                "Chess960 #1",
+                8_146_062,
+                5,
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                "Chess960 #2",
+                16_253_601,
+                5,
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                "Chess960 #3",
+                6_417_013,
+                5,
+            ),
         ];
 
         let mut failures = Vec::new();
@@ -263,7 +255,7 @@ mod tests {
 
         for (fen, desc, expected, depth) in PERFT_SUITE {
             println!("\nTesting: {} ({})", desc, fen);
-            let mut board = Board::from_fen(fen);
+            let mut board = Board::from_fen(fen).unwrap();
             let start = Instant::now();
             let nodes = board.perft::<BULK>(depth);
             let duration = start.elapsed();
@@ -323,5 +315,10 @@ mod tests {
                 );
             }
         }
+        assert!(
+            failures.is_empty(),
+            "{} perft positions failed",
+            failures.len()
+        );
     }
 }
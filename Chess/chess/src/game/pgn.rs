@@ -0,0 +1,384 @@
+use crate::game::{
+    board::{Board, FenError},
+    moves::Move,
+    piece::Colour,
+    san::{parse_san, to_san, SanError},
+};
+use std::fmt;
+use std::fs;
+use std::iter::Peekable;
+use std::str::Chars;
+
+const USAGE: &str = "Usage: chess pgn <file>";
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+/// Tags every exported game carries, in the order the PGN standard requires
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+const LINE_WIDTH: usize = 80;
+
+#[derive(Debug)]
+pub enum PgnError {
+    Tag(usize),
+    Fen { line: usize, err: FenError },
+    Move { line: usize, err: SanError },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Tag(line) => write!(f, "line {}: malformed tag pair", line),
+            PgnError::Fen { line, err } => write!(f, "line {}: invalid FEN tag, {}", line, err),
+            PgnError::Move { line, err } => write!(f, "line {}: {}", line, err),
+        }
+    }
+}
+
+/// A game as read from or written to PGN, variations and comments are not kept
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    pub moves: Vec<Move>,
+    pub result: String,
+}
+
+impl Game {
+    pub fn new(start: Board) -> Self {
+        Self {
+            tags: Vec::new(),
+            start,
+            moves: Vec::new(),
+            result: "*".to_string(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Every position of the game, from the starting one to the one after the last move
+    pub fn positions(&self) -> Vec<Board> {
+        let mut board = self.start;
+        let mut positions = vec![board];
+
+        for &m in &self.moves {
+            board.make_move(m);
+            positions.push(board);
+        }
+
+        positions
+    }
+
+    /// Exports the game, the seven tag roster first and unknown roster tags as `?`. `SetUp` and
+    /// `FEN` are derived from the starting position rather than taken from the tags
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => &self.result,
+                "Date" => self.tag(name).unwrap_or("????.??.??"),
+                _ => self.tag(name).unwrap_or("?"),
+            };
+            pgn.push_str(&tag_pair(name, value));
+        }
+
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.contains(&name.as_str()) && name != "SetUp" && name != "FEN" {
+                pgn.push_str(&tag_pair(name, value));
+            }
+        }
+
+        let fen = self.start.to_fen();
+        if fen != START_FEN {
+            pgn.push_str(&tag_pair("SetUp", "1"));
+            pgn.push_str(&tag_pair("FEN", &fen));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut board = self.start;
+        for (ply, &m) in self.moves.iter().enumerate() {
+            match board.side {
+                Colour::White => tokens.push(format!("{}.", board.fullmoves)),
+                Colour::Black if ply == 0 => tokens.push(format!("{}...", board.fullmoves)),
+                Colour::Black => {}
+            }
+            tokens.push(to_san(&board, m));
+            board.make_move(m);
+        }
+        tokens.push(self.result.clone());
+
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > LINE_WIDTH {
+                pgn.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            line_len += token.len();
+            pgn.push_str(&token);
+        }
+        pgn.push('\n');
+
+        pgn
+    }
+}
+
+fn tag_pair(name: &str, value: &str) -> String {
+    format!(
+        "[{} \"{}\"]\n",
+        name,
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// Characters of a PGN file along with the line they are on, for error reporting
+struct Reader<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Reader<'_> {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_until(&mut self, end: char) {
+        while self.next().is_some_and(|c| c != end) {}
+    }
+
+    /// Skips a variation, which may hold comments and variations of its own
+    fn skip_variation(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some('(') => depth += 1,
+                Some(')') => depth -= 1,
+                Some('{') => self.skip_until('}'),
+                Some(';') => self.skip_until('\n'),
+                Some(_) => {}
+                None => return,
+            }
+        }
+    }
+
+    fn tag_pair(&mut self) -> Option<(String, String)> {
+        let mut text = String::new();
+        let mut escaped = false;
+        let mut quoted = false;
+
+        loop {
+            let c = self.next()?;
+            match c {
+                ']' if !quoted => break,
+                '\n' => return None,
+                '"' if !escaped => quoted = !quoted,
+                _ => {}
+            }
+            escaped = c == '\\' && !escaped;
+            text.push(c);
+        }
+
+        let (name, value) = text.trim().split_once(char::is_whitespace)?;
+        let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+        Some((
+            name.to_string(),
+            value.replace("\\\"", "\"").replace("\\\\", "\\"),
+        ))
+    }
+
+    fn token(&mut self, first: char) -> String {
+        let mut token = first.to_string();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() || "[]{}();".contains(c) {
+                break;
+            }
+            token.push(c);
+            self.next();
+        }
+        token
+    }
+}
+
+/// Reads every game of a PGN file. Comments, NAGs and variations are skipped and the moves of the
+/// main line are replayed, so any illegal or ambiguous move is reported with its line
+pub fn parse_pgn(text: &str) -> Result<Vec<Game>, PgnError> {
+    let mut reader = Reader {
+        chars: text.chars().peekable(),
+        line: 1,
+    };
+    let mut games = Vec::new();
+    let mut tags: Vec<(String, String)> = Vec::new();
+    let mut game: Option<(Game, Board)> = None;
+
+    while let Some(c) = reader.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '[' => {
+                // A game missing its result ends where the next one starts
+                if let Some((finished, _)) = game.take() {
+                    games.push(finished);
+                }
+                let line = reader.line;
+                tags.push(reader.tag_pair().ok_or(PgnError::Tag(line))?);
+            }
+            '{' => reader.skip_until('}'),
+            ';' | '%' => reader.skip_until('\n'),
+            '(' => reader.skip_variation(),
+            _ => {
+                let token = reader.token(c);
+                let san = strip_move_number(&token);
+                if san.is_empty() || san.starts_with('$') {
+                    continue;
+                }
+
+                if game.is_none() {
+                    let start = match tags.iter().find(|(name, _)| name == "FEN") {
+                        Some((_, fen)) => Board::from_fen(fen).map_err(|err| PgnError::Fen {
+                            line: reader.line,
+                            err,
+                        })?,
+                        None => Board::default(),
+                    };
+                    let mut started = Game::new(start);
+                    started.tags = std::mem::take(&mut tags);
+                    game = Some((started, start));
+                }
+                let (current, board) = game.as_mut().unwrap();
+
+                if RESULTS.contains(&token.as_str()) {
+                    current.result = token;
+                    games.extend(game.take().map(|(finished, _)| finished));
+                    continue;
+                }
+
+                let m = parse_san(board, san).map_err(|err| PgnError::Move {
+                    line: reader.line,
+                    err,
+                })?;
+                current.moves.push(m);
+                board.make_move(m);
+            }
+        }
+    }
+
+    games.extend(game.map(|(finished, _)| finished));
+    Ok(games)
+}
+
+/// Replays every game of a PGN file and prints the FEN of each position reached, one per line
+pub fn run(args: &[String]) {
+    let [path] = args else {
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    };
+
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+
+    match parse_pgn(&text) {
+        Ok(games) => {
+            for board in games.iter().flat_map(Game::positions) {
+                println!("{}", board.to_fen());
+            }
+        }
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Removes the move number written before a move, such as `12.` or `12...`. Digits not
+/// followed by a dot belong to the move itself, as in `0-0`
+fn strip_move_number(token: &str) -> &str {
+    let digits = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    let rest = digits.trim_start_matches('.');
+    if digits.len() < token.len() && rest.len() < digits.len() {
+        rest
+    } else {
+        token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pgn_round_trip() {
+        let text = r#"[Event "Casual \"blitz\""]
+[White "Oxide"]
+[Black "Oxide"]
+
+1. e4 e5 {Open game} 2. Nf3 (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6 $1 3. Bc4 Nf6?!
+4. Ng5 d5 5. exd5 Na5 6. Bb5+ c6 7. dxc6 bxc6 8. Qf3 cxb5 1-0
+
+[FEN "6k1/5ppp/8/8/8/8/8/R3K3 b - - 3 40"]
+
+40... g6 41. Ra8+ Kg7 *
+
+[FEN "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"]
+
+1. 0-0 0-0-0 2.Rf7 1/2-1/2
+"#;
+        let games = parse_pgn(text).unwrap();
+        assert_eq!(games.len(), 3);
+
+        let game = &games[0];
+        assert_eq!(game.tag("Event"), Some("Casual \"blitz\""));
+        assert_eq!(game.moves.len(), 16);
+        assert_eq!(game.result, "1-0");
+        assert_eq!(
+            game.positions().last().unwrap().to_fen(),
+            "r1bqkb1r/p4ppp/5n2/np2p1N1/8/5Q2/PPPP1PPP/RNB1K2R w KQkq - 0 9"
+        );
+
+        // Exporting and reading the game again gives back the same moves
+        let pgn = game.to_pgn();
+        assert!(pgn.starts_with("[Event \"Casual \\\"blitz\\\"\"]\n[Site \"?\"]\n"));
+        assert!(pgn.contains("\n1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Ng5 d5 5. exd5 Na5 6. Bb5+ c6"));
+        assert_eq!(parse_pgn(&pgn).unwrap()[0].moves, game.moves);
+
+        let pgn = games[1].to_pgn();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"6k1/5ppp/8/8/8/8/8/R3K3 b - - 3 40\"]"));
+        assert!(pgn.ends_with("\n40... g6 41. Ra8+ Kg7 *\n"));
+
+        // Castling written with zeros, exported with letters
+        let game = &games[2];
+        assert_eq!(game.moves.len(), 3);
+        assert_eq!(game.result, "1/2-1/2");
+        assert!(game.to_pgn().ends_with("\n1. O-O O-O-O 2. Rf7 1/2-1/2\n"));
+
+        assert!(matches!(
+            parse_pgn("1. e4 e5 2. Ke3"),
+            Err(PgnError::Move {
+                line: 1,
+                err: SanError::Illegal(_)
+            })
+        ));
+    }
+}
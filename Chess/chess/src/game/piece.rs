@@ -64,16 +64,12 @@ impl Piece {
     ///
     /// * `value` - The FEN character (e.g., 'P' for White Pawn, 'k' for Black King).
     ///
-    /// # Panics
-    ///
-    /// Panics if the character is not a valid FEN piece representation.
-    pub fn from_fen(value: char) -> Self {
-        Self::from(
-            PIECE_CHAR
-                .iter()
-                .position(|&ch| ch == value)
-                .expect("Not found FEN char"),
-        )
+    /// Returns `None` if the character is not a valid FEN piece representation.
+    pub fn from_fen(value: char) -> Option<Self> {
+        PIECE_CHAR
+            .iter()
+            .position(|&ch| ch == value)
+            .map(Self::from)
     }
 
     /// Returns the piece type index, ignoring color (e.g., both WP and BP return 0).
@@ -1,12 +1,37 @@
 use crate::game::{
-    bitboard::BitBoard,
-    board::Board,
+    board::{Board, Undo},
     moves::Move,
-    piece::{Colour, Piece},
+    zobrist::ZHash,
 };
 
 #[derive(Clone, Debug)]
 pub struct Position {
     pub board: Board,
-    pub history: Vec<Board>,
+    /// Hashes of every position reached before the current one, oldest first
+    pub history: Vec<ZHash>,
+}
+
+impl Position {
+    pub fn make_move(&mut self, m: Move) -> Undo {
+        self.history.push(self.board.hash);
+        self.board.make_move(m)
+    }
+
+    pub fn unmake_move(&mut self, m: Move, undo: Undo) {
+        self.history.pop();
+        self.board.unmake_move(m, undo);
+    }
+
+    /// Whether the current position already appeared twice since the last irreversible move
+    pub fn is_threefold(&self) -> bool {
+        self.history
+            .iter()
+            .rev()
+            .take(self.board.halfmoves as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|&&hash| hash == self.board.hash)
+            .count()
+            >= 2
+    }
 }
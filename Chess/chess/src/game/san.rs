@@ -0,0 +1,282 @@
+use crate::game::{
+    board::Board,
+    moves::{Move, MoveKind},
+    piece::Colour,
+    square::Square,
+};
+use std::fmt;
+
+/// Piece letters used by SAN, indexed by piece type
+const SAN_PIECES: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SanError {
+    Syntax(String),
+    Illegal(String),
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::Syntax(san) => write!(f, "invalid move notation {}", san),
+            SanError::Illegal(san) => write!(f, "illegal move {}", san),
+            SanError::Ambiguous(san) => write!(f, "ambiguous move {}", san),
+        }
+    }
+}
+
+/// Writes a legal move in Standard Algebraic Notation, disambiguating by file, then rank, then
+/// both, and appending `+` or `#` when the move gives check or mate
+pub fn to_san(board: &Board, m: Move) -> String {
+    let (src, dest, kind) = (m.get_source(), m.get_dest(), m.get_type());
+    let piece = board.piece_at(src).expect("No piece on the move source");
+    let mut san = String::new();
+
+    if kind == MoveKind::Castle {
+        san.push_str(if dest.col() > src.col() {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        if piece.is_pawn() {
+            if kind.is_capture() {
+                san.push(file_char(src));
+            }
+        } else {
+            san.push(SAN_PIECES[piece.index()]);
+
+            let rivals: Vec<Square> = board
+                .generate_legal_moves()
+                .into_iter()
+                .filter(|o| o.get_dest() == dest && o.get_source() != src)
+                .map(Move::get_source)
+                .filter(|&other| board.piece_at(other) == Some(piece))
+                .collect();
+
+            if !rivals.is_empty() {
+                if rivals.iter().all(|other| other.col() != src.col()) {
+                    san.push(file_char(src));
+                } else if rivals.iter().all(|other| other.row() != src.row()) {
+                    san.push(rank_char(src));
+                } else {
+                    san.push_str(&src.to_string());
+                }
+            }
+        }
+
+        if kind.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&dest.to_string());
+
+        if kind.is_promotion() {
+            san.push('=');
+            san.push(SAN_PIECES[kind.get_promotion(Colour::White).index()]);
+        }
+    }
+
+    let mut next = *board;
+    next.make_move(m);
+    if next.is_attacked_by(next.king_square(next.side), !next.side) {
+        san.push(if next.generate_legal_moves().is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+
+    san
+}
+
+/// Finds the legal move a SAN string refers to. Check, mate and annotation suffixes are ignored,
+/// as is a missing or superfluous capture mark, and castling may be written with zeros
+pub fn parse_san(board: &Board, san: &str) -> Result<Move, SanError> {
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+    let legal = board.generate_legal_moves();
+
+    let candidates: Vec<Move> = match text {
+        "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+            let kingside = text.len() == 3;
+            legal
+                .into_iter()
+                .filter(|m| {
+                    m.get_type() == MoveKind::Castle
+                        && (m.get_dest().col() > m.get_source().col()) == kingside
+                })
+                .collect()
+        }
+        _ => {
+            let pattern =
+                SanPattern::parse(text).ok_or_else(|| SanError::Syntax(san.to_string()))?;
+            legal
+                .into_iter()
+                .filter(|&m| pattern.matches(board, m))
+                .collect()
+        }
+    };
+
+    match candidates.as_slice() {
+        [m] => Ok(*m),
+        [] => Err(SanError::Illegal(san.to_string())),
+        _ => Err(SanError::Ambiguous(san.to_string())),
+    }
+}
+
+/// The parts of a non-castling SAN move, with piece and promotion given as piece type indices
+struct SanPattern {
+    piece: usize,
+    file: Option<usize>,
+    rank: Option<usize>,
+    dest: Square,
+    promotion: Option<usize>,
+}
+
+impl SanPattern {
+    fn parse(text: &str) -> Option<Self> {
+        let mut chars: Vec<char> = text.chars().collect();
+
+        let piece = match chars.first().and_then(|&c| piece_index(c)) {
+            Some(piece) => {
+                chars.remove(0);
+                piece
+            }
+            None => 0,
+        };
+
+        let mut promotion = None;
+        if piece == 0 {
+            if let Some(promo) = chars.last().and_then(|&c| piece_index(c)) {
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+                promotion = Some(promo);
+            }
+        }
+
+        if chars.len() < 2 {
+            return None;
+        }
+        let rank = chars.pop()?;
+        let file = chars.pop()?;
+        let dest = Square::from_row_col(rank_index(rank)?, file_index(file)?);
+
+        if chars.last() == Some(&'x') {
+            chars.pop();
+        }
+
+        let (mut from_file, mut from_rank) = (None, None);
+        match chars.as_slice() {
+            [] => {}
+            [c] if file_index(*c).is_some() => from_file = file_index(*c),
+            [c] => from_rank = Some(rank_index(*c)?),
+            [f, r] => {
+                from_file = Some(file_index(*f)?);
+                from_rank = Some(rank_index(*r)?);
+            }
+            _ => return None,
+        }
+
+        if promotion.is_some_and(|promo| promo == 0 || promo == 5) {
+            return None;
+        }
+
+        Some(Self {
+            piece,
+            file: from_file,
+            rank: from_rank,
+            dest,
+            promotion,
+        })
+    }
+
+    fn matches(&self, board: &Board, m: Move) -> bool {
+        let (src, kind) = (m.get_source(), m.get_type());
+        let promotion = kind
+            .is_promotion()
+            .then(|| kind.get_promotion(Colour::White).index());
+
+        kind != MoveKind::Castle
+            && m.get_dest() == self.dest
+            && board.piece_at(src).map(|piece| piece.index()) == Some(self.piece)
+            && self.file.is_none_or(|file| file == src.col())
+            && self.rank.is_none_or(|rank| rank == src.row())
+            && promotion == self.promotion
+    }
+}
+
+fn piece_index(c: char) -> Option<usize> {
+    SAN_PIECES[1..].iter().position(|&p| p == c).map(|i| i + 1)
+}
+
+fn file_index(c: char) -> Option<usize> {
+    ('a'..='h').contains(&c).then(|| c as usize - 'a' as usize)
+}
+
+fn rank_index(c: char) -> Option<usize> {
+    ('1'..='8').contains(&c).then(|| c as usize - '1' as usize)
+}
+
+fn file_char(square: Square) -> char {
+    (b'a' + square.col() as u8) as char
+}
+
+fn rank_char(square: Square) -> char {
+    (b'1' + square.row() as u8) as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_san_round_trip() {
+        for (fen, uci, san) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "g1f3",
+                "Nf3",
+            ),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1c1", "O-O-O"),
+            ("r3k2r/8/8/8/8/8/8/R4K1R b kq - 0 1", "e8g8", "O-O+"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", "exd6"),
+            ("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8q", "axb8=Q+"),
+            ("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1", "a1d1", "Rad1"),
+            ("3rk3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3", "R1a3"),
+            ("4k3/8/8/8/8/Q1Q5/8/Q3K3 w - - 0 1", "a3b2", "Qa3b2"),
+            ("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", "a1a8", "Ra8#"),
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let m = *board
+                .generate_legal_moves()
+                .iter()
+                .find(|m| m.to_string() == uci)
+                .unwrap();
+            assert_eq!(to_san(&board, m), san, "{}", fen);
+            assert_eq!(parse_san(&board, san), Ok(m), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_san_errors() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K2R w K - 0 1").unwrap();
+        assert_eq!(parse_san(&board, "0-0").unwrap().to_string(), "e1g1");
+        assert_eq!(parse_san(&board, "Ra1d1").unwrap().to_string(), "a1d1");
+        assert_eq!(
+            parse_san(&board, "O-O-O"),
+            Err(SanError::Illegal("O-O-O".to_string()))
+        );
+        assert_eq!(
+            parse_san(&board, "Rz9"),
+            Err(SanError::Syntax("Rz9".to_string()))
+        );
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1").unwrap();
+        assert_eq!(
+            parse_san(&board, "Rd1"),
+            Err(SanError::Ambiguous("Rd1".to_string()))
+        );
+    }
+}
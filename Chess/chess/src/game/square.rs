@@ -52,6 +52,18 @@ impl Square {
         Self::new((row * 8 + col) as usize)
     }
 
+    /// Parses a square in algebraic notation, returning `None` for anything other than a valid
+    /// square, unlike [`Square::from`] which expects well-formed input.
+    pub fn parse(pos: &str) -> Option<Self> {
+        match pos.as_bytes() {
+            &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some(Self::from_row_col(
+                (rank - b'1') as usize,
+                (file - b'a') as usize,
+            )),
+            _ => None,
+        }
+    }
+
     /// Creates a new square from a 0-63 index.
     pub const fn new(index: usize) -> Self {
         Self(index as u8)
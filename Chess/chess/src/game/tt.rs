@@ -3,6 +3,18 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use crate::game::moves::Move;
 use crate::game::zobrist::ZHash;
 
+/// Deepest ply the search can reach
+pub const MAX_PLY: usize = 128;
+/// Score of mating right away, a mate found `n` plies away scores `MATE - n`
+pub const MATE: i32 = 32000;
+/// Lowest score of a mate found at any ply
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+/// Score of a tablebase win, below every mate score
+pub const TB_WIN: i32 = MATE_BOUND - MAX_PLY as i32;
+/// Lowest score of a tablebase win found at any ply. Mate and tablebase scores, beyond it, are
+/// relative to the ply they were found at and stored relative to the position instead
+const TB_WIN_BOUND: i32 = TB_WIN - MAX_PLY as i32;
+
 #[repr(u8)]
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Debug, Hash, Default)]
 pub enum TTFlag {
@@ -44,8 +56,6 @@ const FLAG_MASK: u64 = 0xC000;
 const MOVE_MASK: u64 = 0xFFFF0000;
 const EVAL_MASK: u64 = 0xFFFF00000000;
 
-const LONGEST_TB_MATE: i32 = 16384;
-
 impl TTEntry {
     pub fn get_depth(self) -> usize {
         self.depth as usize
@@ -69,9 +79,9 @@ impl TTEntry {
 
     pub fn get_value(self, ply: usize) -> i32 {
         let value = self.value as i32;
-        if value >= LONGEST_TB_MATE {
+        if value >= TB_WIN_BOUND {
             value - ply as i32
-        } else if value <= -LONGEST_TB_MATE {
+        } else if value <= -TB_WIN_BOUND {
             value + ply as i32
         } else {
             value
@@ -97,7 +107,9 @@ impl From<(u64, u64)> for TTEntry {
             key: key ^ data,
             age: (data & AGE_MASK) as u8,
             depth: ((data & DEPTH_MASK) >> DEPTH_OFFSET) as u8,
-            flag: unsafe { std::mem::transmute(((data & FLAG_MASK) >> FLAG_OFFSET) as u8) },
+            flag: unsafe {
+                std::mem::transmute::<u8, TTFlag>(((data & FLAG_MASK) >> FLAG_OFFSET) as u8)
+            },
             best_move: Move(((data & MOVE_MASK) >> MOVE_OFFSET) as u16),
             eval: ((data & EVAL_MASK) >> EVAL_OFFSET) as i16,
             value: (data >> VALUE_OFFSET) as i16,
@@ -164,6 +176,11 @@ impl TranspositionTable {
         self.table.resize_with(new_size, AtomicField::default);
     }
 
+    #[cfg(test)]
+    pub fn size_mb(&self) -> usize {
+        self.table.len() * std::mem::size_of::<AtomicField>() / (1024 * 1024)
+    }
+
     pub fn clear(&mut self) {
         self.age = 0;
         self.table.iter().for_each(|entry| {
@@ -176,6 +193,22 @@ impl TranspositionTable {
         self.age = (self.age + 1) & 0b01111111;
     }
 
+    /// Permille of the sampled entries written during the current search, as UCI `hashfull`
+    pub fn hashfull(&self) -> usize {
+        let sample = self.table.len().min(1000);
+        if sample == 0 {
+            return 0;
+        }
+
+        let used = self.table[..sample]
+            .iter()
+            .map(AtomicField::read_unchecked)
+            .filter(|entry| entry.key != 0 && entry.age == self.age)
+            .count();
+
+        used * 1000 / sample
+    }
+
     pub fn probe(&self, hash: ZHash) -> Option<TTEntry> {
         unsafe { self.table.get_unchecked(self.get_key(hash)).read(hash) }
     }
@@ -200,9 +233,9 @@ impl TranspositionTable {
             || flag == TTFlag::Exact
             || depth + 2 > old.depth as usize
         {
-            let tt_value = if value >= LONGEST_TB_MATE {
+            let tt_value = if value >= TB_WIN_BOUND {
                 (value + ply as i32) as i16
-            } else if value <= -LONGEST_TB_MATE {
+            } else if value <= -TB_WIN_BOUND {
                 (value - ply as i32) as i16
             } else {
                 value as i16
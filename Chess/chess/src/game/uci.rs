@@ -1,20 +1,41 @@
-use crate::engine::search::find_best_move;
+use crate::engine::bench::{self, DEFAULT_BENCH_DEPTH};
+use crate::engine::book::Book;
+use crate::engine::network::Network;
+use crate::engine::search::{find_best_move, SearchOptions, SearchSignals};
+use crate::engine::syzygy::Tablebases;
+use crate::engine::time::SearchLimits;
 use std::env;
 use std::io::BufRead;
+use std::sync::{atomic::Ordering, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use super::{
-    board::Board,
-    moves::{Move, MoveKind},
-    position::Position,
-    square::Square,
+    bitboard::BitBoard, board::Board, moves::Move, position::Position, tt::TranspositionTable,
 };
 
 const NAME: &str = "Oxide";
 const AUTHOR: &str = env!("CARGO_PKG_AUTHORS");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const DEFAULT_DEPTH: usize = 6;
+const MAX_HASH: usize = 65536; // MB
+const MAX_THREADS: usize = 256;
+const MAX_MULTIPV: usize = 256;
+const MAX_MOVE_OVERHEAD: u64 = 5000; // ms
+const EMBEDDED_EVAL_FILE: &str = "<embedded>";
+const EMPTY_PATH: &str = "<empty>";
 
 pub struct UCIEngine {
     position: Position,
+    tt: Arc<TranspositionTable>,
+    network: Arc<Network>,
+    tablebases: Arc<Tablebases>,
+    book: Option<Book>,
+    /// Play from the book while it has moves for the position, instead of searching
+    own_book: bool,
+    options: SearchOptions,
+    signals: Arc<SearchSignals>,
+    search_thread: Option<JoinHandle<()>>,
 }
 
 impl UCIEngine {
@@ -24,6 +45,14 @@ impl UCIEngine {
                 board: Board::default(),
                 history: Vec::new(),
             },
+            tt: Arc::new(TranspositionTable::new(TranspositionTable::DEFAULT_SIZE)),
+            network: Arc::from(Network::embedded()),
+            tablebases: Arc::new(Tablebases::default()),
+            book: None,
+            own_book: false,
+            options: SearchOptions::default(),
+            signals: Arc::new(SearchSignals::default()),
+            search_thread: None,
         }
     }
 
@@ -38,6 +67,8 @@ impl UCIEngine {
 
             self.process_command(&line);
         }
+
+        self.stop_search();
     }
 
     fn process_command(&mut self, command: &str) {
@@ -50,6 +81,7 @@ impl UCIEngine {
             "uci" => {
                 println!("id name {} {}", NAME, VERSION);
                 println!("id author {}", AUTHOR);
+                self.print_options();
                 println!("uciok");
             }
             "ucinewgame" => {
@@ -57,17 +89,35 @@ impl UCIEngine {
                     board: Board::default(),
                     history: Vec::new(),
                 };
+                self.stop_search();
+                self.tt_mut().clear();
             }
             "isready" => {
                 println!("readyok");
             }
+            "setoption" => {
+                self.set_option(&parts[1..]);
+            }
             "position" => {
                 self.parse_position(&parts[1..]);
             }
             "go" => {
                 self.go(&parts[1..]);
             }
+            "stop" => {
+                self.stop_search();
+            }
+            "ponderhit" => {
+                self.signals.ponder_hit();
+            }
+            "d" => self.print_position(),
+            "bench" => {
+                let depth = parts.get(1).and_then(|depth| depth.parse().ok());
+                self.stop_search();
+                bench::run(&self.network, depth.unwrap_or(DEFAULT_BENCH_DEPTH));
+            }
             "quit" => {
+                self.stop_search();
                 std::process::exit(0);
             }
             _ => println!("Unexpected command"),
@@ -75,7 +125,7 @@ impl UCIEngine {
     }
 
     fn parse_position(&mut self, args: &[&str]) {
-        let mut board = if args[0] == "startpos" {
+        let board = if args[0] == "startpos" {
             Board::default()
         } else if args[0] == "fen" {
             let fen_end = args
@@ -83,71 +133,413 @@ impl UCIEngine {
                 .position(|&x| x == "moves")
                 .unwrap_or(args.len());
             let fen = args[1..fen_end].join(" ");
-            Board::from_fen(&fen)
+            match Board::from_fen(&fen) {
+                Ok(board) => board,
+                Err(err) => {
+                    println!("info string Invalid FEN {}: {}", fen, err);
+                    return;
+                }
+            }
         } else {
             return; // Comando inválido
         };
 
+        let mut position = Position {
+            board,
+            history: Vec::new(),
+        };
+
         let moves_start = args.iter().position(|&x| x == "moves");
         if let Some(start) = moves_start {
             for move_str in &args[start + 1..] {
-                let m = self.parse_move(&board, move_str);
-                board.make_move(m);
+                let Some(m) = self.parse_move(&position.board, move_str) else {
+                    println!("info string Illegal move {}", move_str);
+                    break;
+                };
+                position.make_move(m);
             }
         }
 
-        self.position.board = board;
-        self.position.history.clear();
+        self.position = position;
     }
 
     fn go(&mut self, args: &[&str]) {
-        let mut depth = 6;
-        for i in 0..args.len() {
-            if args[i] == "depth" && i + 1 < args.len() {
-                depth = args[i + 1].parse().unwrap_or(depth);
-            }
-        }
-
-        let best_move = find_best_move(&self.position.board, depth);
-        println!("bestmove {}", best_move);
-    }
-
-    fn parse_move(&self, board: &Board, move_str: &str) -> Move {
-        let src = Square::from(&move_str[0..2]);
-        let dest = Square::from(&move_str[2..4]);
-        let promotion = move_str.get(4..5);
-
-        let moves = board.generate_legal_moves();
-        for m in moves {
-            if m.get_source() == src && m.get_dest() == dest {
-                if let Some(promo_char) = promotion {
-                    let promo_piece = match promo_char {
-                        "q" => MoveKind::QueenPromotion,
-                        "r" => MoveKind::RookPromotion,
-                        "b" => MoveKind::BishopPromotion,
-                        "n" => MoveKind::KnightPromotion,
-                        _ => continue,
-                    };
-                    if m.get_type() == promo_piece || m.get_type() == promo_piece.with_capture() {
-                        return m;
-                    }
-                } else if !m.get_type().is_promotion() {
-                    return m;
-                }
+        let limits = Self::parse_limits(args);
+
+        self.stop_search();
+
+        // A book move would be reported at once, which UCI forbids before `stop` or `ponderhit`
+        // in these modes, so they search instead
+        if !limits.ponder && !limits.infinite {
+            if let Some(m) = self.book_move() {
+                println!("bestmove {}", m.to_uci(self.options.chess960));
+                return;
             }
         }
-        Move::default() // Fallback
+
+        self.tt_mut().increment_age();
+        self.signals.start(limits.ponder);
+
+        let position = self.position.clone();
+        let options = self.options;
+        let tt = Arc::clone(&self.tt);
+        let network = Arc::clone(&self.network);
+        let tablebases = Arc::clone(&self.tablebases);
+        let signals = Arc::clone(&self.signals);
+
+        self.search_thread = Some(thread::spawn(move || {
+            let result = find_best_move(
+                &position,
+                &limits,
+                &options,
+                &tt,
+                &network,
+                &tablebases,
+                &signals,
+            );
+            println!("bestmove {}", result.best_move.to_uci(options.chess960));
+        }));
+    }
+
+    fn print_options(&self) {
+        let defaults = SearchOptions::default();
+        println!(
+            "option name Hash type spin default {} min 1 max {}",
+            TranspositionTable::DEFAULT_SIZE,
+            MAX_HASH
+        );
+        println!(
+            "option name Threads type spin default {} min 1 max {}",
+            defaults.threads, MAX_THREADS
+        );
+        println!("option name Clear Hash type button");
+        println!(
+            "option name EvalFile type string default {}",
+            EMBEDDED_EVAL_FILE
+        );
+        println!("option name SyzygyPath type string default {}", EMPTY_PATH);
+        println!("option name OwnBook type check default false");
+        println!("option name UCI_Chess960 type check default false");
+        println!("option name BookFile type string default {}", EMPTY_PATH);
+        println!(
+            "option name MultiPV type spin default {} min 1 max {}",
+            defaults.multipv, MAX_MULTIPV
+        );
+        println!(
+            "option name Move Overhead type spin default {} min 0 max {}",
+            defaults.move_overhead, MAX_MOVE_OVERHEAD
+        );
+
+        for (name, default) in [
+            ("NullMovePruning", defaults.null_move),
+            ("LMR", defaults.lmr),
+            ("ReverseFutilityPruning", defaults.reverse_futility),
+            ("FutilityPruning", defaults.futility),
+            ("Razoring", defaults.razoring),
+            ("CheckExtensions", defaults.check_extensions),
+        ] {
+            println!("option name {} type check default {}", name, default);
+        }
+    }
+
+    /// Handles `setoption name <id> [value <x>]`, option names are case insensitive and values
+    /// outside the advertised range are rejected
+    fn set_option(&mut self, args: &[&str]) {
+        let value_idx = args.iter().position(|&x| x == "value");
+        let name = args[1.min(args.len())..value_idx.unwrap_or(args.len())]
+            .join(" ")
+            .to_lowercase();
+        let value = value_idx.map(|i| args[i + 1..].join(" "));
+        let number = value.as_deref().and_then(|v| v.parse::<u64>().ok());
+        let check = value.as_deref().and_then(|v| v.parse::<bool>().ok());
+
+        self.stop_search();
+
+        match (name.as_str(), number, check) {
+            ("hash", Some(mb), _) if (1..=MAX_HASH as u64).contains(&mb) => {
+                self.tt_mut().resize(mb as usize);
+                self.tt_mut().clear();
+            }
+            ("threads", Some(threads), _) if (1..=MAX_THREADS as u64).contains(&threads) => {
+                self.options.threads = threads as usize;
+            }
+            ("clear hash", _, _) => self.tt_mut().clear(),
+            ("multipv", Some(lines), _) if (1..=MAX_MULTIPV as u64).contains(&lines) => {
+                self.options.multipv = lines as usize;
+            }
+            ("move overhead", Some(ms), _) if ms <= MAX_MOVE_OVERHEAD => {
+                self.options.move_overhead = ms;
+            }
+            ("nullmovepruning", _, Some(on)) => self.options.null_move = on,
+            ("lmr", _, Some(on)) => self.options.lmr = on,
+            ("reversefutilitypruning", _, Some(on)) => self.options.reverse_futility = on,
+            ("futilitypruning", _, Some(on)) => self.options.futility = on,
+            ("razoring", _, Some(on)) => self.options.razoring = on,
+            ("checkextensions", _, Some(on)) => self.options.check_extensions = on,
+            // Without a value the embedded network is restored
+            ("evalfile", _, _) => self.load_network(value.as_deref().unwrap_or(EMBEDDED_EVAL_FILE)),
+            ("ownbook", _, Some(on)) => self.own_book = on,
+            ("uci_chess960", _, Some(on)) => self.options.chess960 = on,
+            ("bookfile", _, _) => self.load_book(value.as_deref().unwrap_or(EMPTY_PATH)),
+            ("syzygypath", _, _) => self.load_tablebases(value.as_deref().unwrap_or(EMPTY_PATH)),
+            _ => println!(
+                "info string Unknown option or invalid value: {}",
+                args.join(" ")
+            ),
+        }
+    }
+
+    /// Replaces the evaluation network, keeping the current one if the file can not be loaded
+    fn load_network(&mut self, path: &str) {
+        if path == EMBEDDED_EVAL_FILE {
+            self.network = Arc::from(Network::embedded());
+            return;
+        }
+
+        match Network::from_file(path) {
+            Ok(network) => {
+                self.network = Arc::from(network);
+                println!("info string Loaded network {}", path);
+            }
+            Err(err) => println!("info string Failed to load network {}: {}", path, err),
+        }
+    }
+
+    /// Replaces the tablebases with the ones found in a list of directories
+    fn load_tablebases(&mut self, paths: &str) {
+        if paths == EMPTY_PATH {
+            self.tablebases = Arc::new(Tablebases::default());
+            return;
+        }
+
+        let tablebases = Tablebases::open(paths);
+        println!(
+            "info string Found {} tablebases, up to {} pieces",
+            tablebases.count(),
+            tablebases.max_pieces()
+        );
+        self.tablebases = Arc::new(tablebases);
+    }
+
+    fn load_book(&mut self, path: &str) {
+        if path == EMPTY_PATH {
+            self.book = None;
+            return;
+        }
+
+        match Book::from_file(path) {
+            Ok(book) => {
+                println!(
+                    "info string Loaded book {} with {} entries",
+                    path,
+                    book.entry_count()
+                );
+                self.book = Some(book);
+            }
+            Err(err) => println!("info string Failed to load book {}: {}", path, err),
+        }
+    }
+
+    /// Prints the board of the current position along with its FEN, hash key and the pieces
+    /// giving check
+    fn print_position(&self) {
+        let board = &self.position.board;
+        let king = board.king_square(board.side);
+        let occupied = board.sides[0] | board.sides[1];
+        let mut checkers = board.attackers_to(king, occupied) & board.sides[!board.side as usize];
+
+        let mut squares = Vec::new();
+        while checkers != BitBoard::EMPTY {
+            let square = checkers.lsb();
+            squares.push(square.to_string());
+            checkers = checkers.pop_bit(square);
+        }
+
+        println!("\n{}", board);
+        println!("Fen: {}", board.to_fen());
+        println!("Key: {:016X}", board.hash.0);
+        println!("Checkers: {}", squares.join(" "));
+    }
+
+    /// Weighted random move from the opening book, if enabled and it knows the position
+    fn book_move(&self) -> Option<Move> {
+        if !self.own_book {
+            return None;
+        }
+
+        let random = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        self.book.as_ref()?.pick(&self.position.board, random)
+    }
+
+    /// Signals the running search, if any, to stop and waits for it to report its best move
+    fn stop_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            self.signals.stop.store(true, Ordering::SeqCst);
+            handle.join().expect("Search thread panicked");
+        }
+    }
+
+    /// Exclusive access to the transposition table, only valid while no search is running
+    fn tt_mut(&mut self) -> &mut TranspositionTable {
+        Arc::get_mut(&mut self.tt).expect("Transposition table is shared with a running search")
+    }
+
+    fn parse_limits(args: &[&str]) -> SearchLimits {
+        let mut limits = SearchLimits::default();
+        let value = |i: usize| args.get(i + 1).and_then(|v| v.parse::<i64>().ok());
+
+        for (i, &arg) in args.iter().enumerate() {
+            match arg {
+                "depth" => limits.depth = value(i).map(|d| d.max(1) as usize),
+                "nodes" => limits.nodes = value(i).map(|n| n.max(1) as u64),
+                "movetime" => limits.movetime = value(i).map(|t| t.max(0) as u64),
+                "wtime" => limits.time[0] = value(i).map(|t| t.max(0) as u64),
+                "btime" => limits.time[1] = value(i).map(|t| t.max(0) as u64),
+                "winc" => limits.inc[0] = value(i).map_or(0, |t| t.max(0) as u64),
+                "binc" => limits.inc[1] = value(i).map_or(0, |t| t.max(0) as u64),
+                "movestogo" => limits.movestogo = value(i).map(|n| n.max(1) as u64),
+                "infinite" => limits.infinite = true,
+                "ponder" => limits.ponder = true,
+                _ => {}
+            }
+        }
+
+        let unlimited = limits.depth.is_none()
+            && limits.nodes.is_none()
+            && limits.movetime.is_none()
+            && limits.time.iter().all(Option::is_none);
+        if unlimited && !limits.infinite && !limits.ponder {
+            limits.depth = Some(DEFAULT_DEPTH);
+        }
+
+        limits
+    }
+
+    /// Finds the legal move written in UCI notation, in which castling is the king taking its
+    /// rook when playing Chess960
+    fn parse_move(&self, board: &Board, move_str: &str) -> Option<Move> {
+        board
+            .generate_legal_moves()
+            .into_iter()
+            .find(|m| m.to_uci(self.options.chess960) == move_str)
     }
 }
 
-impl MoveKind {
-    fn with_capture(self) -> Self {
-        match self {
-            MoveKind::KnightPromotion => MoveKind::KnightCapPromo,
-            MoveKind::BishopPromotion => MoveKind::BishopCapPromo,
-            MoveKind::RookPromotion => MoveKind::RookCapPromo,
-            MoveKind::QueenPromotion => MoveKind::QueenCapPromo,
-            _ => self,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_history() {
+        let mut engine = UCIEngine::new();
+        let shuffle = "g1f3 g8f6 f3g1 f6g8";
+
+        engine.process_command(&format!(
+            "position startpos moves {} g1f3 g8f6 f3g1",
+            shuffle
+        ));
+        assert_eq!(engine.position.history.len(), 7);
+        assert!(!engine.position.is_threefold());
+
+        engine.process_command(&format!("position startpos moves {} {}", shuffle, shuffle));
+        assert_eq!(engine.position.history.len(), 8);
+        assert!(engine.position.is_threefold());
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let parse = |args: &str| {
+            let args: Vec<&str> = args.split_whitespace().collect();
+            UCIEngine::parse_limits(&args)
+        };
+
+        let limits = parse("wtime 60000 btime 55000 winc 1000 binc 500 movestogo 20");
+        assert_eq!(limits.time, [Some(60_000), Some(55_000)]);
+        assert_eq!(limits.inc, [1_000, 500]);
+        assert_eq!(limits.movestogo, Some(20));
+        assert_eq!(limits.depth, None);
+
+        let limits = parse("movetime 2500");
+        assert_eq!(limits.movetime, Some(2_500));
+        assert_eq!(limits.depth, None);
+
+        let limits = parse("depth 8 nodes 100000");
+        assert_eq!(limits.depth, Some(8));
+        assert_eq!(limits.nodes, Some(100_000));
+
+        // Negative clocks are clamped, missing values are ignored
+        let limits = parse("wtime -50 winc");
+        assert_eq!(limits.time[0], Some(0));
+        assert_eq!(limits.inc[0], 0);
+
+        assert!(parse("infinite").infinite);
+        assert_eq!(parse("infinite").depth, None);
+        assert_eq!(parse("").depth, Some(DEFAULT_DEPTH));
+    }
+
+    #[test]
+    fn test_set_option() {
+        let mut engine = UCIEngine::new();
+
+        engine.process_command("setoption name Hash value 1");
+        assert_eq!(engine.tt.size_mb(), 1);
+        engine.process_command("setoption name Hash value 0");
+        engine.process_command(&format!("setoption name Hash value {}", MAX_HASH + 1));
+        assert_eq!(engine.tt.size_mb(), 1);
+        engine.process_command("setoption name Clear Hash");
+
+        engine.process_command("setoption name Threads value 4");
+        assert_eq!(engine.options.threads, 4);
+        engine.process_command("setoption name threads value 0");
+        engine.process_command(&format!("setoption name Threads value {}", MAX_THREADS + 1));
+        assert_eq!(engine.options.threads, 4);
+
+        engine.process_command("setoption name MultiPV value 3");
+        assert_eq!(engine.options.multipv, 3);
+        engine.process_command("setoption name MultiPV value 0");
+        engine.process_command(&format!("setoption name MultiPV value {}", MAX_MULTIPV + 1));
+        assert_eq!(engine.options.multipv, 3);
+
+        engine.process_command("setoption name Move Overhead value 0");
+        assert_eq!(engine.options.move_overhead, 0);
+        engine.process_command("setoption name Move Overhead value 100");
+        engine.process_command("setoption name Move Overhead value -1");
+        engine.process_command(&format!(
+            "setoption name Move Overhead value {}",
+            MAX_MOVE_OVERHEAD + 1
+        ));
+        assert_eq!(engine.options.move_overhead, 100);
+
+        for name in [
+            "NullMovePruning",
+            "LMR",
+            "ReverseFutilityPruning",
+            "FutilityPruning",
+            "Razoring",
+            "CheckExtensions",
+        ] {
+            engine.process_command(&format!("setoption name {} value false", name));
+            engine.process_command(&format!("setoption name {} value maybe", name));
         }
+        let options = engine.options;
+        assert!(!options.null_move && !options.lmr && !options.reverse_futility);
+        assert!(!options.futility && !options.razoring && !options.check_extensions);
+
+        engine.process_command("setoption name UCI_Chess960 value true");
+        assert!(engine.options.chess960);
+
+        engine.process_command("setoption name OwnBook value true");
+        assert!(engine.own_book);
+        engine.process_command("setoption name BookFile value missing.bin");
+        assert!(engine.book.is_none());
+        engine.process_command("setoption name SyzygyPath value missing");
+        assert_eq!(engine.tablebases.count(), 0);
+
+        // Unknown options and unparsable values leave everything untouched
+        engine.process_command("setoption name Contempt value 20");
+        engine.process_command("setoption name Threads value many");
+        assert_eq!(engine.options.threads, 4);
     }
 }
@@ -1,7 +1,7 @@
 use super::{
     board::Board,
     castle::CastlingRights,
-    constants::{CASTLE_KEYS, EP_KEYS, PIECE_KEYS, SIDE_KEY},
+    constants::{CASTLE_KEYS, EP_KEYS, PIECE_KEYS, POLYGLOT_KEYS, SIDE_KEY},
     piece::{Colour, Piece},
     square::Square,
 };
@@ -55,3 +55,57 @@ impl ZHash {
         self.0 ^= SIDE_KEY
     }
 }
+
+const POLYGLOT_CASTLE: usize = 768;
+const POLYGLOT_EP: usize = 772;
+const POLYGLOT_SIDE: usize = 780;
+
+/// Hash of a position as used by Polyglot opening books. Unlike `ZHash` the en passant file is
+/// only hashed when a pawn of the side to move can actually capture there
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+
+    for square in 0..Square::COUNT {
+        if let Some(piece) = board.piece_at(Square::new(square)) {
+            let kind = 2 * piece.index() + (piece.colour() == Colour::White) as usize;
+            key ^= POLYGLOT_KEYS[64 * kind + square];
+        }
+    }
+
+    for (i, right) in [
+        CastlingRights::WK,
+        CastlingRights::WQ,
+        CastlingRights::BK,
+        CastlingRights::BQ,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if board.castling_rights.0 & right != 0 {
+            key ^= POLYGLOT_KEYS[POLYGLOT_CASTLE + i];
+        }
+    }
+
+    if let Some(square) = board.en_passant {
+        let pawn = if board.side == Colour::White {
+            Piece::WP
+        } else {
+            Piece::BP
+        };
+        let capturer = |file_delta| {
+            square
+                .jump(file_delta, -board.side.forward())
+                .is_some_and(|from| board.piece_at(from) == Some(pawn))
+        };
+
+        if capturer(-1) || capturer(1) {
+            key ^= POLYGLOT_KEYS[POLYGLOT_EP + square.col()];
+        }
+    }
+
+    if board.side == Colour::White {
+        key ^= POLYGLOT_KEYS[POLYGLOT_SIDE];
+    }
+
+    key
+}
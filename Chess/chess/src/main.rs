@@ -1,9 +1,21 @@
+use engine::{bench::DEFAULT_BENCH_DEPTH, network::Network};
 use game::uci::UCIEngine;
 
 mod engine;
 mod game;
 
 fn main() {
-    let mut engine = UCIEngine::new();
-    engine.run();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("bench") => {
+            let depth = args.get(1).and_then(|depth| depth.parse().ok());
+            engine::bench::run(&Network::embedded(), depth.unwrap_or(DEFAULT_BENCH_DEPTH));
+        }
+        Some("datagen") => engine::datagen::run(&args[1..]),
+        Some("epd") => engine::epd::run(&args[1..]),
+        Some("pgn") => game::pgn::run(&args[1..]),
+        Some("play") => engine::play::run(&args[1..]),
+        _ => UCIEngine::new().run(),
+    }
 }